@@ -8,10 +8,47 @@ pub fn parse_args<'a>() -> Args<'a> {
         (version: "0.1.0")
         (about: "Process tracer")
         (@arg TUI: -t --tui "Interactive TUI")
+        (@arg FOLD_DEPTH: --("fold-depth") +takes_value {is_number}
+            "Number of tree levels initially shown in the TUI")
+        (@arg THEME: --theme +takes_value
+            "Colours of the TUI, one of dark, light, 16, none or a JSON theme file \
+             [default: ~/.config/race/theme.json if present, none with NO_COLOR, otherwise dark]")
+        (@arg YANK: --yank +takes_value
+            "Where y and Y in the TUI copy to: clipboard via the terminal, stdout when quitting, \
+             or a file [default: clipboard]")
+        (@arg LISTEN: --listen +takes_value "Streams trace events as JSON lines to clients of this unix socket")
         (@arg OUTFILE: -o +takes_value "Dumps tree to file")
+        (@arg PRINT: -p --print "Prints tree to stdout, default without other output")
+        (@arg COLOR: --color +takes_value possible_value[auto always never] default_value("auto")
+            "Colors printed tree, auto honours NO_COLOR and only colors terminals")
+        (@arg STYLE: --style +takes_value possible_value[unicode ascii forest indent] default_value("unicode")
+            "Style of the tree guides")
+        (@arg SORT: --sort +takes_value possible_value[pid start duration size status name]
+            "Orders children in the tree, by default they are in fork order")
+        (@arg SORT_ORDER: --("sort-order") +takes_value possible_value[asc desc] default_value("asc")
+            "Sorts ascending or descending")
+        (@arg NO_MARKERS: --("no-markers") "Hides [+]/[-] expand markers")
+        (@arg CHROME: --chrome +takes_value "Exports trace in Chrome trace event format")
+        (@arg CHROME_TRACK: --("chrome-track") +takes_value possible_value[depth lane] default_value("lane")
+            "Assigns processes to timeline tracks by tree depth or in non-overlapping lanes")
+        (@arg COMPDB: --compdb +takes_value "Exports compiler invocations as compile_commands.json")
+        (@arg COMPILERS: --compilers +takes_value +use_delimiter
+            "Comma separated compiler names for --compdb and --script [default: cc,c++,gcc,g++,clang,clang++]")
+        (@arg SCRIPT: --script +takes_value "Exports leaf commands as replayable shell script")
+        (@arg SCRIPT_ROOT: --("script-root") +takes_value "Pid of the subtree to export as script")
+        (@arg FOLDED: --folded +takes_value "Exports folded stacks for flamegraph tools")
+        (@arg FOLDED_WEIGHT: --("folded-weight") +takes_value possible_value[wall cpu] default_value("wall")
+            "Weights stacks by own wall time or cpu time")
         (@group INPUT +required =>
             (@arg INFILE: -i +takes_value "Input json file")
             (@arg PROGRAM: ... "Program to trace")
         )
     ).get_matches()
 }
+
+fn is_number(value: String) -> Result<(), String> {
+    value
+        .parse::<usize>()
+        .map(|_| ())
+        .map_err(|_| format!("'{}' is not a number", value))
+}
@@ -0,0 +1,228 @@
+use serde_derive::Serialize;
+
+use crate::process::tree::{NodeId, ProcessTree};
+use crate::process::{Pid, ProcessData, Timestamp};
+use crate::Result;
+
+use std::io;
+
+/// How processes are assigned to tracks in the timeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Track {
+    /// One track per tree depth. Siblings running in parallel overlap.
+    Depth,
+    /// Greedily packed tracks, processes on one track never overlap
+    Lane,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trace<'a> {
+    trace_events: Vec<Event<'a>>,
+    display_time_unit: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct Event<'a> {
+    name: &'a str,
+    cat: &'static str,
+    ph: &'static str,
+    ts: Timestamp,
+    dur: Timestamp,
+    pid: Pid,
+    tid: usize,
+    args: EventArgs<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct EventArgs<'a> {
+    pid: Pid,
+    argv: &'a [String],
+}
+
+/// Writes the tree in the Trace Event format understood by `chrome://tracing` and Perfetto
+///
+/// Every process with known start and end time yields one complete event, and every image
+/// it exec'd yields one nested complete event on the same track.
+pub fn write<W: io::Write>(tree: &ProcessTree, track: Track, w: W) -> Result<()> {
+    let origin = tree
+        .iter()
+        .filter_map(|path| tree.get(*path.last().unwrap()).data().start_time())
+        .min()
+        .unwrap_or(0);
+
+    let mut events = Vec::new();
+    for (id, tid) in assign_tracks(tree, track) {
+        let data = tree.get(id).data();
+        let (start, end) = match (data.start_time(), data.end_time()) {
+            (Some(start), Some(end)) => (start, end),
+            _ => continue,
+        };
+
+        events.push(Event {
+            name: data.program(),
+            cat: "process",
+            ph: "X",
+            ts: start - origin,
+            dur: end.saturating_sub(start),
+            pid: 0,
+            tid,
+            args: EventArgs {
+                pid: data.pid(),
+                argv: data.argv().unwrap_or(&[]),
+            },
+        });
+
+        let execs = data.execs();
+        for (idx, exec) in execs.iter().enumerate() {
+            let exec_end = execs.get(idx + 1).map_or(end, |next| next.time());
+            events.push(Event {
+                name: exec.program(),
+                cat: "exec",
+                ph: "X",
+                ts: exec.time() - origin,
+                dur: exec_end.saturating_sub(exec.time()),
+                pid: 0,
+                tid,
+                args: EventArgs {
+                    pid: data.pid(),
+                    argv: exec.argv(),
+                },
+            });
+        }
+    }
+
+    let trace = Trace {
+        trace_events: events,
+        display_time_unit: "ms",
+    };
+    serde_json::to_writer(w, &trace)?;
+
+    Ok(())
+}
+
+fn assign_tracks(tree: &ProcessTree, track: Track) -> Vec<(NodeId, usize)> {
+    match track {
+        Track::Depth => tree
+            .iter()
+            .map(|path| (*path.last().unwrap(), path.len() - 1))
+            .collect(),
+        Track::Lane => {
+            let mut ids: Vec<NodeId> = tree.iter().map(|path| *path.last().unwrap()).collect();
+            ids.sort_by_key(|&id| (tree.get(id).data().start_time(), id));
+
+            let mut lane_ends: Vec<Timestamp> = Vec::new();
+            ids.into_iter()
+                .map(|id| {
+                    let data: &ProcessData = tree.get(id).data();
+                    let start = data.start_time().unwrap_or(0);
+                    let end = data.end_time().unwrap_or(start);
+                    let lane = match lane_ends.iter().position(|&lane_end| lane_end <= start) {
+                        Some(lane) => lane,
+                        None => {
+                            lane_ends.push(0);
+                            lane_ends.len() - 1
+                        }
+                    };
+                    lane_ends[lane] = end;
+                    (id, lane)
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::export::testutil::process;
+
+    fn make_tree() -> ProcessTree {
+        let mut t = ProcessTree::new(process(1, "/src", &["sh -c make"], 1000, 1100));
+        let make = t.insert(process(2, "/src", &["make"], 1010, 1090), Some(0));
+        t.insert(process(3, "/src", &["cc a.c"], 1020, 1040), Some(make));
+        t.insert(process(4, "/src", &["cc b.c"], 1030, 1050), Some(make));
+        t.insert(
+            process(5, "/src", &["/bin/sh", "/usr/bin/ld"], 1060, 1070),
+            Some(make),
+        );
+        t
+    }
+
+    fn export(track: Track) -> serde_json::Value {
+        let mut buf = Vec::new();
+        write(&make_tree(), track, &mut buf).unwrap();
+        serde_json::from_slice(&buf).unwrap()
+    }
+
+    fn tids(trace: &serde_json::Value, cat: &str) -> Vec<(u64, u64)> {
+        trace["traceEvents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|ev| ev["cat"] == cat)
+            .map(|ev| {
+                (
+                    ev["args"]["pid"].as_u64().unwrap(),
+                    ev["tid"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn events() {
+        let trace = export(Track::Depth);
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 11);
+
+        let root = &events[0];
+        assert_eq!(root["name"], "sh");
+        assert_eq!(root["ph"], "X");
+        assert_eq!(root["ts"], 0);
+        assert_eq!(root["dur"], 100);
+        assert_eq!(
+            root["args"]["argv"],
+            serde_json::json!(["sh", "-c", "make"])
+        );
+
+        let ld: Vec<_> = events
+            .iter()
+            .filter(|ev| ev["args"]["pid"] == 5)
+            .map(|ev| {
+                (
+                    ev["name"].as_str().unwrap(),
+                    ev["ts"].as_u64().unwrap(),
+                    ev["dur"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            ld,
+            vec![("ld", 60, 10), ("sh", 60, 1), ("ld", 61, 9)]
+        );
+    }
+
+    #[test]
+    fn depth_tracks() {
+        let trace = export(Track::Depth);
+        assert_eq!(
+            tids(&trace, "process"),
+            vec![(1, 0), (2, 1), (3, 2), (4, 2), (5, 2)]
+        );
+    }
+
+    #[test]
+    fn lane_tracks() {
+        let trace = export(Track::Lane);
+        assert_eq!(
+            tids(&trace, "process"),
+            vec![(1, 0), (2, 1), (3, 2), (4, 3), (5, 2)]
+        );
+        assert_eq!(
+            tids(&trace, "exec"),
+            vec![(1, 0), (2, 1), (3, 2), (4, 3), (5, 2), (5, 2)]
+        );
+    }
+}
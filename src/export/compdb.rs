@@ -0,0 +1,218 @@
+use serde_derive::Serialize;
+
+use crate::process::tree::ProcessTree;
+use crate::process::ExecData;
+use crate::Result;
+
+use std::io;
+
+pub const DEFAULT_COMPILERS: &[&str] = &["cc", "c++", "gcc", "g++", "clang", "clang++"];
+
+/// Programs that run the compiler given as their first argument
+const WRAPPERS: &[&str] = &["ccache", "sccache", "distcc", "icecc"];
+
+const SOURCE_EXTENSIONS: &[&str] = &[
+    "c", "cc", "cp", "cpp", "cxx", "c++", "C", "CC", "CPP", "m", "mm", "i", "ii", "s", "S", "sx",
+    "cu",
+];
+
+/// Options whose value is a separate argument that must not be taken for a source file
+const OPTIONS_WITH_VALUE: &[&str] = &["-o", "-MF", "-MT", "-MQ", "-include", "-imacros"];
+
+/// Options that make the compiler stop before compiling anything
+const NON_COMPILING_OPTIONS: &[&str] = &["-E", "-M", "-MM"];
+
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    directory: &'a str,
+    arguments: &'a [String],
+    file: &'a str,
+}
+
+/// Writes a `compile_commands.json` with one entry per source file of every compiler invocation
+///
+/// Children of a compiler invocation, like `cc1` or `as` spawned by the driver, or the real
+/// compiler run by a wrapper, are not reported again.
+pub fn write<W: io::Write, S: AsRef<str>>(tree: &ProcessTree, compilers: &[S], w: W) -> Result<()> {
+    let mut entries = Vec::new();
+    let mut compiler_depth: Option<usize> = None;
+    for path in tree.iter() {
+        match compiler_depth {
+            Some(depth) if path.len() > depth => continue,
+            _ => compiler_depth = None,
+        }
+
+        let mut found = false;
+        for exec in tree.get(*path.last().unwrap()).data().execs() {
+            if let Some(arguments) = compiler_arguments(exec, compilers) {
+                found = true;
+                let directory = match exec.cwd() {
+                    Some(cwd) => cwd,
+                    None => continue,
+                };
+                entries.extend(source_files(arguments).map(|file| Entry {
+                    directory,
+                    arguments,
+                    file,
+                }));
+            }
+        }
+
+        if found {
+            compiler_depth = Some(path.len());
+        }
+    }
+
+    serde_json::to_writer_pretty(w, &entries)?;
+
+    Ok(())
+}
+
+/// Returns the compiler command line, without wrapper, if `exec` is a compiler invocation
+pub fn compiler_arguments<'a, S: AsRef<str>>(
+    exec: &'a ExecData,
+    compilers: &[S],
+) -> Option<&'a [String]> {
+    let mut argv = exec.argv();
+    if WRAPPERS.contains(&basename(argv.first()?)) {
+        argv = &argv[1..];
+    }
+
+    let program = strip_version(basename(argv.first()?));
+    let is_compiler = compilers.iter().any(|name| {
+        let name = name.as_ref();
+        program == name
+            || (program.ends_with(name) && program[..program.len() - name.len()].ends_with('-'))
+    });
+
+    if is_compiler
+        && !argv
+            .iter()
+            .any(|a| NON_COMPILING_OPTIONS.contains(&a.as_str()))
+    {
+        Some(argv)
+    } else {
+        None
+    }
+}
+
+fn source_files(arguments: &[String]) -> impl Iterator<Item = &str> {
+    let mut skip_next = true;
+    arguments.iter().filter_map(move |arg| {
+        if skip_next {
+            skip_next = false;
+            return None;
+        }
+        if OPTIONS_WITH_VALUE.contains(&arg.as_str()) {
+            skip_next = true;
+            return None;
+        }
+
+        let ext = arg.rsplit('.').next()?;
+        if !arg.starts_with('-') && ext != arg && SOURCE_EXTENSIONS.contains(&ext) {
+            Some(arg.as_str())
+        } else {
+            None
+        }
+    })
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Strips version suffixes like in `gcc-8` or `clang-7.0`
+fn strip_version(program: &str) -> &str {
+    match program.rfind('-') {
+        Some(idx)
+            if idx + 1 < program.len()
+                && program[idx + 1..]
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c == '.') =>
+        {
+            &program[..idx]
+        }
+        _ => program,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::export::testutil::process;
+
+    fn export(tree: &ProcessTree, compilers: &[&str]) -> serde_json::Value {
+        let mut buf = Vec::new();
+        write(tree, compilers, &mut buf).unwrap();
+        serde_json::from_slice(&buf).unwrap()
+    }
+
+    #[test]
+    fn entries() {
+        let mut t = ProcessTree::new(process(0, "/src", &["make all"], 0, 0));
+        let gcc = t.insert(process(0, "/src", &["gcc -c a.c -o a.o"], 0, 0), Some(0));
+        t.insert(
+            process(0, "/src", &["/usr/lib/gcc/cc1 a.c -o /tmp/a.s"], 0, 0),
+            Some(gcc),
+        );
+        t.insert(process(0, "/src", &["as /tmp/a.s -o a.o"], 0, 0), Some(gcc));
+        let ccache = t.insert(
+            process(0, "/src/sub", &["ccache g++-8 -c b.cpp c.cc"], 0, 0),
+            Some(0),
+        );
+        t.insert(process(0, "/src/sub", &["g++-8 -c b.cpp c.cc"], 0, 0), Some(ccache));
+        t.insert(
+            process(0, "/src", &["arm-none-eabi-gcc -c -MF d.c.d d.c"], 0, 0),
+            Some(0),
+        );
+        t.insert(process(0, "/src", &["gcc a.o b.o -o prog"], 0, 0), Some(0));
+        t.insert(process(0, "/src", &["gcc -E conftest.c"], 0, 0), Some(0));
+        t.insert(process(0, "/src", &["nvcc -c e.cu"], 0, 0), Some(0));
+
+        assert_eq!(
+            export(&t, DEFAULT_COMPILERS),
+            serde_json::json!([
+                {
+                    "directory": "/src",
+                    "arguments": ["gcc", "-c", "a.c", "-o", "a.o"],
+                    "file": "a.c",
+                },
+                {
+                    "directory": "/src/sub",
+                    "arguments": ["g++-8", "-c", "b.cpp", "c.cc"],
+                    "file": "b.cpp",
+                },
+                {
+                    "directory": "/src/sub",
+                    "arguments": ["g++-8", "-c", "b.cpp", "c.cc"],
+                    "file": "c.cc",
+                },
+                {
+                    "directory": "/src",
+                    "arguments": ["arm-none-eabi-gcc", "-c", "-MF", "d.c.d", "d.c"],
+                    "file": "d.c",
+                },
+            ])
+        );
+
+        assert_eq!(
+            export(&t, &["nvcc"]),
+            serde_json::json!([
+                {
+                    "directory": "/src",
+                    "arguments": ["nvcc", "-c", "e.cu"],
+                    "file": "e.cu",
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn version_suffix() {
+        assert_eq!(strip_version("gcc-8"), "gcc");
+        assert_eq!(strip_version("clang-7.0"), "clang");
+        assert_eq!(strip_version("arm-none-eabi-gcc"), "arm-none-eabi-gcc");
+        assert_eq!(strip_version("gcc-"), "gcc-");
+    }
+}
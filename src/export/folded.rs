@@ -0,0 +1,81 @@
+use crate::process::tree::ProcessTree;
+use crate::Result;
+
+use std::io;
+
+/// What a stack is weighted by, both in microseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weight {
+    /// Wall time of the process minus the wall time of its children
+    Wall,
+    /// User and system time of the process itself
+    Cpu,
+}
+
+/// Writes the tree in folded stacks format, as consumed by `flamegraph.pl` and friends
+///
+/// Each process yields one line with the `;` separated program names from the root down to it,
+/// followed by its own weight. Processes without weight are left out.
+pub fn write<W: io::Write>(tree: &ProcessTree, weight: Weight, mut w: W) -> Result<()> {
+    for path in tree.iter() {
+        let node = tree.get(*path.last().unwrap());
+        let value = match weight {
+            Weight::Wall => {
+                node.children()
+                    .iter()
+                    .fold(node.data().duration().unwrap_or(0), |value, &child| {
+                        value.saturating_sub(tree.get(child).data().duration().unwrap_or(0))
+                    })
+            }
+            Weight::Cpu => node.data().cpu_time().unwrap_or(0),
+        };
+        if value == 0 {
+            continue;
+        }
+
+        let stack: Vec<String> = path
+            .iter()
+            .map(|&id| tree.get(id).data().program().replace(';', ":"))
+            .collect();
+        writeln!(w, "{} {}", stack.join(";"), value)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::export::testutil::process;
+    use crate::process::Rusage;
+
+    fn export(tree: &ProcessTree, weight: Weight) -> String {
+        let mut buf = Vec::new();
+        write(tree, weight, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn stacks() {
+        let mut t = ProcessTree::new(process(0, "/", &["make"], 0, 100));
+        let sh = t.insert(process(0, "/", &["/bin/sh"], 10, 60), Some(0));
+        let gcc = t.insert(process(0, "/", &["/usr/bin/gcc"], 10, 60), Some(sh));
+        let odd = t.insert(process(0, "/", &["odd;name"], 60, 70), Some(0));
+        for &(id, user_time) in &[(0, 5), (gcc, 40), (odd, 8)] {
+            t.get_mut(id).data_mut().set_rusage(Rusage {
+                user_time,
+                ..Rusage::default()
+            });
+        }
+
+        assert_eq!(
+            export(&t, Weight::Wall),
+            "make 40\nmake;sh;gcc 50\nmake;odd:name 10\n"
+        );
+        assert_eq!(
+            export(&t, Weight::Cpu),
+            "make 5\nmake;sh;gcc 40\nmake;odd:name 8\n"
+        );
+    }
+}
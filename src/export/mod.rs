@@ -0,0 +1,31 @@
+pub mod chrome;
+pub mod compdb;
+pub mod folded;
+pub mod script;
+
+/// Fixtures shared by the exporter and process tree tests
+#[cfg(test)]
+pub mod testutil {
+    use crate::process::{ExecData, Pid, ProcessData, Timestamp};
+
+    /// Process running from `start` to `end` which exec'd the space separated `cmdlines`
+    /// in `cwd`, one microsecond apart starting at `start`
+    pub fn process(
+        pid: Pid,
+        cwd: &str,
+        cmdlines: &[&str],
+        start: Timestamp,
+        end: Timestamp,
+    ) -> ProcessData {
+        let mut data = ProcessData::new(pid);
+        data.set_start_time(start);
+        data.set_end_time(end);
+        for (idx, cmdline) in cmdlines.iter().enumerate() {
+            let argv = cmdline.split(' ').map(str::to_string).collect();
+            let mut exec = ExecData::new(start + idx as Timestamp, argv);
+            exec.set_cwd(cwd);
+            data.add_exec(exec);
+        }
+        data
+    }
+}
@@ -0,0 +1,127 @@
+use crate::export::compdb;
+use crate::process::tree::{NodeId, ProcessTree};
+use crate::util::quote;
+use crate::Result;
+
+use std::io;
+
+/// Writes a POSIX shell script re-running the leaf commands below `root` in DFS order
+///
+/// A process is a leaf if none of its descendants exec'd, so shells, wrappers and build drivers
+/// whose work is covered by their children are skipped. Invocations of `compilers` are leaves
+/// as well, the `cc1` or `as` they spawn are not run again. Every command is run in a subshell
+/// with the recorded cwd and environment, where known.
+pub fn write<W: io::Write, S: AsRef<str>>(
+    tree: &ProcessTree,
+    root: NodeId,
+    compilers: &[S],
+    mut w: W,
+) -> Result<()> {
+    let paths: Vec<_> = tree.iter_subtree(root).collect();
+
+    let mut covered = vec![false; tree.num_nodes()];
+    for path in paths.iter().rev().filter(|path| path.len() > 1) {
+        let id = *path.last().unwrap();
+        if covered[id] || !tree.get(id).data().execs().is_empty() {
+            covered[path[path.len() - 2]] = true;
+        }
+    }
+
+    writeln!(w, "#!/bin/sh")?;
+    writeln!(
+        w,
+        "# Generated by race from the trace of pid {}",
+        tree.get(root).data().pid()
+    )?;
+
+    let mut leaf_depth: Option<usize> = None;
+    for path in &paths {
+        match leaf_depth {
+            Some(depth) if path.len() > depth => continue,
+            _ => leaf_depth = None,
+        }
+
+        let id = *path.last().unwrap();
+        let data = tree.get(id).data();
+        let exec = match data.execs().last() {
+            Some(exec)
+                if !covered[id] || compdb::compiler_arguments(exec, compilers).is_some() =>
+            {
+                exec
+            }
+            _ => continue,
+        };
+        leaf_depth = Some(path.len());
+
+        writeln!(w)?;
+        writeln!(w, "# pid {}", data.pid())?;
+        write!(w, "(")?;
+        if let Some(cwd) = exec.cwd() {
+            write!(w, "cd {} && ", quote(cwd))?;
+        }
+        write!(w, "exec")?;
+        if exec.env_changes().is_some() {
+            write!(w, " env -i")?;
+            for var in tree.env_after(id, data.execs().len()) {
+                write!(w, " {}", quote(&var))?;
+            }
+        }
+        for arg in exec.argv() {
+            write!(w, " {}", quote(arg))?;
+        }
+        writeln!(w, ")")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::export::testutil::process;
+
+    #[test]
+    fn script() {
+        let mut t = ProcessTree::new(process(1, "/src dir", &["make"], 0, 0));
+        let sh = t.insert(process(2, "/src dir", &["sh -c gcc"], 0, 0), Some(0));
+        let gcc = t.insert(process(3, "/src dir", &["gcc -c a.c"], 0, 0), Some(sh));
+        t.insert(process(4, "/src dir", &["cc1 a.c"], 0, 0), Some(gcc));
+        t.insert(process(5, "/src dir", &["as a.s"], 0, 0), Some(gcc));
+        t.insert(process(6, "/src dir", &[], 0, 0), Some(0));
+        t.insert(process(7, "/src dir", &["sh", "echo it's"], 0, 0), Some(0));
+        // Children inherit the environment of make
+        for id in 0..t.num_nodes() {
+            for exec in t.get_mut(id).data_mut().execs_mut() {
+                exec.set_env_changes(match id {
+                    0 => vec!["+PATH=/bin".to_string(), "+LANG=C".to_string()],
+                    _ => Vec::new(),
+                });
+            }
+        }
+
+        let mut buf = Vec::new();
+        write(&t, 0, compdb::DEFAULT_COMPILERS, &mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "#!/bin/sh
+# Generated by race from the trace of pid 1
+
+# pid 3
+(cd '/src dir' && exec env -i PATH=/bin LANG=C gcc -c a.c)
+
+# pid 7
+(cd '/src dir' && exec env -i PATH=/bin LANG=C echo 'it'\\''s')
+"
+        );
+
+        let mut buf = Vec::new();
+        write(&t, gcc, compdb::DEFAULT_COMPILERS, &mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap().lines().count(), 5);
+
+        // Without known compilers the driver's children are the leaves
+        let mut buf = Vec::new();
+        write(&t, gcc, &[] as &[&str], &mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap().lines().count(), 8);
+    }
+}
@@ -1,12 +1,18 @@
 mod args;
+mod export;
 mod process;
 mod race;
+mod server;
 mod tui;
 mod util;
 
-use crate::process::tree::ProcessTree;
+use crate::process::tree::{OrderedTree, ProcessTree};
+use crate::tui::theme::Theme;
+use crate::tui::tree_style::TreeStyle;
+use crate::tui::tv::{Sort, SortKey};
 use crate::tui::{term, tv};
 
+use std::env;
 use std::fs;
 use std::io;
 use std::io::Write;
@@ -46,6 +52,11 @@ fn main() {
             }
             Ok(race) => race,
         };
+        if let Some(socket) = args.value_of("LISTEN") {
+            if let Err(e) = race.listen(socket) {
+                eprintln!("Cannot listen on socket {}: {}", socket, e);
+            }
+        }
         race.trace();
         race.into_tree()
     } else {
@@ -74,15 +85,28 @@ fn main() {
         }
     }
 
+    let mut style = args
+        .value_of("STYLE")
+        .and_then(TreeStyle::from_name)
+        .unwrap_or_default();
+    style.markers = !args.is_present("NO_MARKERS");
+    let sort = args
+        .value_of("SORT")
+        .and_then(SortKey::from_name)
+        .map(|key| Sort {
+            key,
+            descending: args.value_of("SORT_ORDER") == Some("desc"),
+        });
+
     if let Some(filename) = args.value_of("OUTFILE") {
         match fs::File::create(filename) {
             Ok(f) => {
                 let mut bw = io::BufWriter::new(f);
-                for l in tv::TreeView::new(&tree).gen_lines() {
-                    if let Err(e) = writeln!(bw, "{}", l) {
-                        eprintln!("Error dumping tree: {}", e);
-                        break;
-                    }
+                let mut tv = tv::TreeView::new(OrderedTree::new(&tree));
+                tv.set_style(style.clone());
+                tv.set_sort(sort);
+                if let Err(e) = tv.write_lines(&mut bw, false, false) {
+                    eprintln!("Error dumping tree: {}", e);
                 }
             }
             Err(e) => {
@@ -91,9 +115,122 @@ fn main() {
         }
     }
 
+    let other_output = ["OUTFILE", "TUI", "CHROME", "COMPDB", "SCRIPT", "FOLDED"]
+        .iter()
+        .any(|arg| args.is_present(arg));
+    if args.is_present("PRINT") || !other_output {
+        let colored = match args.value_of("COLOR") {
+            Some("always") => true,
+            Some("never") => false,
+            _ => {
+                termion::is_tty(&io::stdout())
+                    && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+        };
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        let mut tv = tv::TreeView::new(OrderedTree::new(&tree));
+        tv.set_style(style.clone());
+        tv.set_sort(sort);
+        if let Err(e) = tv.write_lines(&mut stdout, colored, true) {
+            eprintln!("Error printing tree: {}", e);
+        }
+    }
+
+    if let Some(filename) = args.value_of("CHROME") {
+        let track = match args.value_of("CHROME_TRACK") {
+            Some("depth") => export::chrome::Track::Depth,
+            _ => export::chrome::Track::Lane,
+        };
+        write_file(filename, |w| export::chrome::write(&tree, track, w));
+    }
+
+    let compilers: Vec<&str> = match args.values_of("COMPILERS") {
+        Some(compilers) => compilers.collect(),
+        None => export::compdb::DEFAULT_COMPILERS.to_vec(),
+    };
+    if let Some(filename) = args.value_of("COMPDB") {
+        write_file(filename, |w| export::compdb::write(&tree, &compilers, w));
+    }
+
+    if let Some(filename) = args.value_of("SCRIPT") {
+        let root = match args.value_of("SCRIPT_ROOT") {
+            Some(pid) => match tree
+                .iter()
+                .map(|path| *path.last().unwrap())
+                .find(|&id| pid.parse() == Ok(tree.get(id).data().pid()))
+            {
+                Some(id) => Some(id),
+                None => {
+                    eprintln!("No process with pid {} in trace", pid);
+                    None
+                }
+            },
+            None => Some(0),
+        };
+        if let Some(root) = root {
+            write_file(filename, |w| export::script::write(&tree, root, &compilers, w));
+        }
+    }
+
+    if let Some(filename) = args.value_of("FOLDED") {
+        let weight = match args.value_of("FOLDED_WEIGHT") {
+            Some("cpu") => export::folded::Weight::Cpu,
+            _ => export::folded::Weight::Wall,
+        };
+        write_file(filename, |w| export::folded::write(&tree, weight, w));
+    }
+
     if args.is_present("TUI") {
-        let tv = tv::TreeView::new(&tree);
-        let mut tui: tui::Tui<_, term::Term> = tui::Tui::new(tv).unwrap();
+        let mut tv = tv::TreeView::new(OrderedTree::new(&tree));
+        tv.set_style(style);
+        tv.set_sort(sort);
+        if let Some(depth) = args.value_of("FOLD_DEPTH") {
+            tv.set_fold_depth(depth.parse().unwrap());
+        }
+        let theme = match args.value_of("THEME") {
+            Some(theme) => Theme::load(theme),
+            None => match Theme::config_path().filter(|p| p.exists()) {
+                Some(path) => Theme::load(&path.to_string_lossy()),
+                None if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) => {
+                    Ok(tui::theme::NONE)
+                }
+                None => Ok(Theme::default()),
+            },
+        };
+        let theme = match theme {
+            Ok(theme) => theme,
+            Err(e) => {
+                eprintln!("Error loading theme: {}", e);
+                return;
+            }
+        };
+        let mut tui: tui::Tui<_, term::Term> = tui::Tui::new(tv, theme).unwrap();
+        tui.set_yank_target(match args.value_of("YANK") {
+            None | Some("clipboard") => tui::YankTarget::Clipboard,
+            Some("stdout") => tui::YankTarget::Stdout,
+            Some(file) => tui::YankTarget::File(file.into()),
+        });
         tui.event_loop();
+        if let Some(text) = tui.into_yanked() {
+            println!("{}", text.trim_end_matches('\n'));
+        }
+    }
+}
+
+fn write_file<F>(filename: &str, export: F)
+where
+    F: FnOnce(&mut io::BufWriter<fs::File>) -> Result<()>,
+{
+    match fs::File::create(filename) {
+        Ok(f) => {
+            let mut bw = io::BufWriter::new(f);
+            if let Err(e) = export(&mut bw).and_then(|_| Ok(bw.flush()?)) {
+                eprintln!("Error writing file {}: {}", filename, e);
+            }
+        }
+        Err(e) => {
+            eprintln!("Error opening file {}: {}", filename, e);
+        }
     }
 }
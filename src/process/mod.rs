@@ -2,15 +2,135 @@ pub mod tree;
 
 use serde_derive::{Deserialize, Serialize};
 
+use crate::util::{self, Span, SpanKind};
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
 use std::io;
+use std::time;
+
+pub type Pid = i32;
 
-type Pid = i32;
+/// Microseconds since the unix epoch
+pub type Timestamp = u64;
+
+pub fn timestamp() -> Timestamp {
+    let since_epoch = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .unwrap_or_default();
+    since_epoch.as_secs() * 1_000_000 + u64::from(since_epoch.subsec_micros())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExecData {
+    time: Timestamp,
+    argv: Vec<String>,
+    #[serde(default)]
+    cwd: Option<String>,
+    #[serde(default)]
+    env_changes: Option<Vec<String>>,
+}
+
+impl ExecData {
+    pub fn new(time: Timestamp, argv: Vec<String>) -> Self {
+        ExecData {
+            time,
+            argv,
+            cwd: None,
+            env_changes: None,
+        }
+    }
+
+    pub fn time(&self) -> Timestamp {
+        self.time
+    }
+
+    pub fn argv(&self) -> &[String] {
+        &self.argv
+    }
+
+    /// Basename of the exec'd program
+    pub fn program(&self) -> &str {
+        let arg0 = self.argv.first().map_or("", String::as_str);
+        arg0.rsplit('/').next().unwrap_or(arg0)
+    }
+
+    /// Working directory at the time of the exec
+    pub fn cwd(&self) -> Option<&str> {
+        self.cwd.as_deref()
+    }
+
+    pub fn set_cwd<T: Into<String>>(&mut self, cwd: T) {
+        self.cwd = Some(cwd.into());
+    }
+
+    /// Changes to the inherited environment at the time of the exec, as listed by `env_diff`
+    ///
+    /// `ProcessTree::env_after` replays them to get the whole environment.
+    pub fn env_changes(&self) -> Option<&[String]> {
+        self.env_changes.as_deref()
+    }
+
+    pub fn set_env_changes(&mut self, changes: Vec<String>) {
+        self.env_changes = Some(changes);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExitStatus {
+    Exited(i32),
+    Signaled(i32),
+}
+
+impl ExitStatus {
+    pub fn success(self) -> bool {
+        self == ExitStatus::Exited(0)
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use nix::sys::signal::Signal;
+
+        match *self {
+            ExitStatus::Exited(code) => write!(f, "exit {}", code),
+            ExitStatus::Signaled(sig) => match Signal::from_c_int(sig) {
+                Ok(sig) => write!(f, "{:?}", sig),
+                Err(_) => write!(f, "signal {}", sig),
+            },
+        }
+    }
+}
+
+/// Resource usage of a process, read right before it exits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rusage {
+    /// Microseconds spent in user mode
+    pub user_time: u64,
+    /// Microseconds spent in kernel mode
+    pub system_time: u64,
+    /// Peak resident set size in kilobytes
+    pub max_rss: u64,
+    pub minor_faults: u64,
+    pub major_faults: u64,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProcessData {
     pid: Pid,
     cmdline: String,
+    #[serde(default)]
+    start_time: Option<Timestamp>,
+    #[serde(default)]
+    end_time: Option<Timestamp>,
+    #[serde(default)]
+    execs: Vec<ExecData>,
+    #[serde(default)]
+    rusage: Option<Rusage>,
+    #[serde(default)]
+    exit_status: Option<ExitStatus>,
 }
 
 impl ProcessData {
@@ -18,37 +138,289 @@ impl ProcessData {
         ProcessData {
             pid,
             cmdline: "UNKNOWN".to_string(),
+            start_time: None,
+            end_time: None,
+            execs: Vec::new(),
+            rusage: None,
+            exit_status: None,
         }
     }
 
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    pub fn start_time(&self) -> Option<Timestamp> {
+        self.start_time
+    }
+
+    pub fn set_start_time(&mut self, time: Timestamp) {
+        self.start_time = Some(time);
+    }
+
+    pub fn end_time(&self) -> Option<Timestamp> {
+        self.end_time
+    }
+
+    pub fn set_end_time(&mut self, time: Timestamp) {
+        self.end_time = Some(time);
+    }
+
+    pub fn rusage(&self) -> Option<Rusage> {
+        self.rusage
+    }
+
+    pub fn set_rusage(&mut self, rusage: Rusage) {
+        self.rusage = Some(rusage);
+    }
+
+    /// User and system time in microseconds
+    pub fn cpu_time(&self) -> Option<u64> {
+        self.rusage.map(|r| r.user_time + r.system_time)
+    }
+
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        self.exit_status
+    }
+
+    pub fn set_exit_status(&mut self, status: ExitStatus) {
+        self.exit_status = Some(status);
+    }
+
+    /// Wall time in microseconds, if the process was seen starting and exiting
+    pub fn duration(&self) -> Option<u64> {
+        Some(self.end_time?.saturating_sub(self.start_time?))
+    }
+
+    pub fn execs(&self) -> &[ExecData] {
+        &self.execs
+    }
+
+    pub fn execs_mut(&mut self) -> &mut [ExecData] {
+        &mut self.execs
+    }
+
+    /// Argument vector of the current image, if the process exec'd at all
+    pub fn argv(&self) -> Option<&[String]> {
+        self.execs.last().map(ExecData::argv)
+    }
+
+    /// Basename of the current image's program
+    pub fn program(&self) -> &str {
+        let arg0 = match self.argv().and_then(|argv| argv.first()) {
+            Some(arg0) => arg0.as_str(),
+            None => self.cmdline.split(' ').next().unwrap_or(""),
+        };
+        arg0.rsplit('/').next().unwrap_or(arg0)
+    }
+
     pub fn read_cmdline(&mut self) -> Result<(), io::Error> {
-        let filename = format!("/proc/{}/cmdline", self.pid);
-        self.cmdline = fs::read_to_string(&filename)?
-            .replace(0 as char, " ")
-            .trim()
-            .to_string();
+        let argv = read_argv(self.pid)?;
+        self.cmdline = argv.join(" ").trim().to_string();
+
+        Ok(())
+    }
+
+    /// Records a new image, called when the process exec'd
+    ///
+    /// Only the changes to `inherited`, the environment before the exec, are kept.
+    pub fn read_exec(&mut self, time: Timestamp, inherited: &[String]) -> Result<(), io::Error> {
+        let mut exec = ExecData::new(time, read_argv(self.pid)?);
+        if let Ok(cwd) = fs::read_link(format!("/proc/{}/cwd", self.pid)) {
+            exec.set_cwd(cwd.to_string_lossy());
+        }
+        if let Ok(env) = read_nul_separated(&format!("/proc/{}/environ", self.pid)) {
+            exec.set_env_changes(env_diff(inherited, &env));
+        }
+        self.add_exec(exec);
 
         Ok(())
     }
+
+    /// Reads resource usage, called when the process is about to exit
+    pub fn read_stat(&mut self) -> Result<(), io::Error> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Malformed stat file");
+
+        let stat = fs::read_to_string(format!("/proc/{}/stat", self.pid))?;
+        // Fields after the parenthesized command name, starting with the state
+        let fields: Vec<&str> = stat
+            .get(stat.rfind(')').ok_or_else(invalid)? + 1..)
+            .ok_or_else(invalid)?
+            .split_whitespace()
+            .collect();
+        let field = |idx: usize| -> Result<u64, io::Error> {
+            fields
+                .get(idx)
+                .and_then(|f| f.parse().ok())
+                .ok_or_else(invalid)
+        };
+
+        let ticks_per_sec = match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+            n if n > 0 => n as u64,
+            _ => 100,
+        };
+        let mut rusage = Rusage {
+            user_time: field(11)? * 1_000_000 / ticks_per_sec,
+            system_time: field(12)? * 1_000_000 / ticks_per_sec,
+            max_rss: 0,
+            minor_faults: field(7)?,
+            major_faults: field(9)?,
+        };
+
+        // Peak RSS is only reported in the status file, e.g. `VmHWM:    1234 kB`
+        let status = fs::read_to_string(format!("/proc/{}/status", self.pid))?;
+        if let Some(hwm) = status.lines().find_map(|l| l.strip_prefix("VmHWM:")) {
+            rusage.max_rss = hwm
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse()
+                .unwrap_or(0);
+        }
+        self.set_rusage(rusage);
+
+        Ok(())
+    }
+
+    pub fn add_exec(&mut self, exec: ExecData) {
+        self.cmdline = exec.argv.join(" ").trim().to_string();
+        self.execs.push(exec);
+    }
 }
 
-pub struct ProcessDataLineIter<'a> {
-    lines: Box<Iterator<Item = &'a str> + 'a>,
+/// Lists variables that were added or changed as `+NAME=value` and removed ones as `-NAME`
+pub fn env_diff<S: AsRef<str>>(old: &[S], new: &[S]) -> Vec<String> {
+    fn split(var: &str) -> (&str, &str) {
+        let mut parts = var.splitn(2, '=');
+        (parts.next().unwrap_or(""), parts.next().unwrap_or(""))
+    }
+    let old: BTreeMap<&str, &str> = old.iter().map(|v| split(v.as_ref())).collect();
+    let new: BTreeMap<&str, &str> = new.iter().map(|v| split(v.as_ref())).collect();
+
+    let mut names: Vec<&str> = old.keys().chain(new.keys()).cloned().collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+        .into_iter()
+        .filter_map(|name| match (old.get(name), new.get(name)) {
+            (Some(a), Some(b)) if a == b => None,
+            (_, Some(value)) => Some(format!("+{}={}", name, value)),
+            (Some(_), None) => Some(format!("-{}", name)),
+            (None, None) => None,
+        })
+        .collect()
 }
 
-impl<'a> ProcessDataLineIter<'a> {
-    fn new(proc_data: &'a ProcessData) -> Self {
+/// Applies `changes` listed by `env_diff` to `env`, added variables come last
+pub fn apply_env_diff<S: AsRef<str>>(env: &[S], changes: &[String]) -> Vec<String> {
+    fn name(var: &str) -> &str {
+        var.split('=').next().unwrap_or("")
+    }
+    let mut env: Vec<String> = env.iter().map(|v| v.as_ref().to_string()).collect();
+    for change in changes {
+        if let Some(var) = change.strip_prefix('+') {
+            match env.iter_mut().find(|v| name(v) == name(var)) {
+                Some(old) => *old = var.to_string(),
+                None => env.push(var.to_string()),
+            }
+        } else if let Some(removed) = change.strip_prefix('-') {
+            env.retain(|v| name(v) != removed);
+        }
+    }
+    env
+}
+
+fn read_argv(pid: Pid) -> Result<Vec<String>, io::Error> {
+    read_nul_separated(&format!("/proc/{}/cmdline", pid))
+}
+
+fn read_nul_separated(filename: &str) -> Result<Vec<String>, io::Error> {
+    Ok(String::from_utf8_lossy(&fs::read(filename)?)
+        .split_terminator(0 as char)
+        .map(str::to_string)
+        .collect())
+}
+
+/// Splits a command line into spans, marking the program, flags, paths and quoted arguments
+fn cmdline_spans(data: &ProcessData) -> Vec<Span> {
+    // Without an exec the process still runs its parent's image, only the command line is known
+    let args: Vec<Cow<str>> = match data.argv() {
+        Some(argv) => argv.iter().map(|arg| util::quote(arg)).collect(),
+        None => data.cmdline.split(' ').map(Cow::Borrowed).collect(),
+    };
+
+    let mut spans = Vec::new();
+    for (idx, arg) in args.into_iter().enumerate() {
+        if idx > 0 {
+            spans.push(Span::new(" ", SpanKind::Text));
+        }
+        if let Cow::Owned(arg) = arg {
+            spans.push(Span::new(arg, SpanKind::Quoted));
+            continue;
+        }
+
+        if idx == 0 {
+            let (dir, program) = arg.split_at(arg.rfind('/').map_or(0, |idx| idx + 1));
+            if !dir.is_empty() {
+                spans.push(Span::new(dir, SpanKind::Path));
+            }
+            spans.push(Span::new(program, SpanKind::Program));
+        } else if arg.starts_with('-') && arg.len() > 1 {
+            match arg.find('=').filter(|_| arg.starts_with("--")) {
+                Some(idx) => {
+                    let (flag, value) = arg.split_at(idx + 1);
+                    spans.push(Span::new(flag, SpanKind::Flag));
+                    spans.push(Span::new(value, arg_kind(value)));
+                }
+                None => spans.push(Span::new(arg, SpanKind::Flag)),
+            }
+        } else {
+            spans.push(Span::new(arg.as_ref(), arg_kind(&arg)));
+        }
+    }
+    spans
+}
+
+fn arg_kind(arg: &str) -> SpanKind {
+    if arg.contains('/') {
+        SpanKind::Path
+    } else {
+        SpanKind::Text
+    }
+}
+
+/// Lines of a node in the tree view, arguments containing newlines span several lines
+///
+/// Other control characters are escaped.
+pub struct ProcessDataLineIter {
+    lines: std::vec::IntoIter<Vec<Span>>,
+}
+
+impl ProcessDataLineIter {
+    fn new(proc_data: &ProcessData) -> Self {
+        let mut lines = vec![Vec::new()];
+        for span in cmdline_spans(proc_data) {
+            for (idx, text) in span.text.split('\n').enumerate() {
+                if idx > 0 {
+                    lines.push(Vec::new());
+                }
+                if !text.is_empty() {
+                    let text = util::escape(text);
+                    lines.last_mut().unwrap().push(Span::new(text, span.kind));
+                }
+            }
+        }
         ProcessDataLineIter {
-            lines: Box::new(proc_data.cmdline.lines()),
+            lines: lines.into_iter(),
         }
     }
 }
 
-impl<'a> Iterator for ProcessDataLineIter<'a> {
-    type Item = String;
+impl Iterator for ProcessDataLineIter {
+    type Item = Vec<Span>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        Some(self.lines.next()?.to_string())
+        self.lines.next()
     }
 }
 
@@ -58,21 +430,77 @@ mod tests {
 
     #[test]
     fn proc_data_line_iter() {
+        let text = |line: Vec<Span>| util::line_text(&line);
+
         let data = ProcessData {
-            pid: 0,
             cmdline: "blablub".to_owned(),
+            ..ProcessData::new(0)
         };
-        let mut iter = ProcessDataLineIter::new(&data);
+        let mut iter = ProcessDataLineIter::new(&data).map(text);
         assert_eq!(iter.next(), Some("blablub".to_string()));
         assert_eq!(iter.next(), None);
 
         let data = ProcessData {
-            pid: 123,
             cmdline: "blab\nlub".to_owned(),
+            ..ProcessData::new(123)
         };
-        let mut iter = ProcessDataLineIter::new(&data);
+        let mut iter = ProcessDataLineIter::new(&data).map(text);
         assert_eq!(iter.next(), Some("blab".to_string()));
         assert_eq!(iter.next(), Some("lub".to_string()));
         assert_eq!(iter.next(), None);
+
+        // Arguments from the exec are quoted, so the newline stays inside the quotes
+        let mut data = ProcessData::new(1);
+        data.add_exec(ExecData::new(0, vec!["echo".to_string(), "a\nb".to_string()]));
+        let lines: Vec<_> = ProcessDataLineIter::new(&data).map(text).collect();
+        assert_eq!(lines, vec!["echo 'a", "b'"]);
+
+        // Other control characters never reach the terminal
+        let mut data = ProcessData::new(1);
+        data.add_exec(ExecData::new(0, vec!["printf".to_string(), "\x1b[2J\t".to_string()]));
+        let lines: Vec<_> = ProcessDataLineIter::new(&data).map(text).collect();
+        assert_eq!(lines, vec![r"printf '\u{1b}[2J\t'"]);
+    }
+
+    #[test]
+    fn cmdline_spans() {
+        let mut data = ProcessData::new(1);
+        let argv = ["/usr/bin/gcc", "-c", "--sysroot=/opt/sys", "-O2", "a b.c", "src/x.c", "-"];
+        data.add_exec(ExecData::new(0, argv.iter().map(|a| a.to_string()).collect()));
+        let spans: Vec<Span> = super::cmdline_spans(&data)
+            .into_iter()
+            .filter(|s| s.text != " ")
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new("/usr/bin/", SpanKind::Path),
+                Span::new("gcc", SpanKind::Program),
+                Span::new("-c", SpanKind::Flag),
+                Span::new("--sysroot=", SpanKind::Flag),
+                Span::new("/opt/sys", SpanKind::Path),
+                Span::new("-O2", SpanKind::Flag),
+                Span::new("'a b.c'", SpanKind::Quoted),
+                Span::new("src/x.c", SpanKind::Path),
+                Span::new("-", SpanKind::Text),
+            ]
+        );
+    }
+
+    #[test]
+    fn env_changes() {
+        let old = ["HOME=/root", "PATH=/bin", "TERM=xterm"];
+        let new = ["PATH=/usr/bin:/bin", "HOME=/root", "CC=gcc", "OPT=a=b"];
+        assert_eq!(
+            env_diff(&old, &new),
+            vec!["+CC=gcc", "+OPT=a=b", "+PATH=/usr/bin:/bin", "-TERM"]
+        );
+        assert!(env_diff(&old, &old).is_empty());
+
+        assert_eq!(
+            apply_env_diff(&old, &env_diff(&old, &new)),
+            vec!["HOME=/root", "PATH=/usr/bin:/bin", "CC=gcc", "OPT=a=b"]
+        );
+        assert_eq!(apply_env_diff(&[] as &[&str], &env_diff(&[], &new)).len(), 4);
     }
 }
@@ -1,7 +1,11 @@
 use serde_derive::{Deserialize, Serialize};
 
-use crate::process::{ProcessData, ProcessDataLineIter};
-use crate::tui::tv::Tree as TVTree;
+use crate::process::{self, ProcessData, ProcessDataLineIter};
+use crate::tui::tv::{Sort, SortKey, Tree as TVTree};
+use crate::util;
+
+use std::cmp;
+use std::rc::Rc;
 
 pub type NodeId = usize;
 pub type ProcessTree = Tree<ProcessData>;
@@ -29,6 +33,14 @@ impl<T> Node<T> {
     pub fn data_mut(&mut self) -> &mut T {
         &mut self.data
     }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -70,35 +82,75 @@ impl<T> Tree<T> {
         self.nodes[parent_id].children.push(id);
     }
 
-    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
-        let parent_id = self.nodes[id].parent?;
-        let mut iter = self.nodes[parent_id].children.iter();
-        iter.find(|i| **i == id)?;
-        Some(*iter.next()?)
-    }
-
     pub fn num_nodes(&self) -> usize {
         self.nodes.len()
     }
 
-    pub fn iter(&self) -> TreeIter<T> {
-        TreeIter::new(&self)
+    pub fn iter(&self) -> TreeIter<'_, T> {
+        TreeIter::new(self, 0)
+    }
+
+    /// Iterates the subtree below `id`, yielding paths starting at `id`
+    pub fn iter_subtree(&self, id: NodeId) -> TreeIter<'_, T> {
+        TreeIter::new(self, id)
     }
 }
 
-#[derive(Debug)]
+impl Tree<ProcessData> {
+    /// Environment of `id` after its first `execs` images, the inherited one for 0
+    ///
+    /// Execs only record changes, so they are replayed starting at the root. A process
+    /// inherits the environment of the parent's image it was forked from.
+    pub fn env_after(&self, id: NodeId, execs: usize) -> Vec<String> {
+        let node = self.get(id);
+        if execs > 0 {
+            let env = self.env_after(id, execs - 1);
+            return match node.data().execs()[execs - 1].env_changes() {
+                Some(changes) => process::apply_env_diff(&env, changes),
+                None => env,
+            };
+        }
+
+        match node.parent() {
+            Some(parent) => {
+                let start = node.data().start_time();
+                let forked_from = self
+                    .get(parent)
+                    .data()
+                    .execs()
+                    .iter()
+                    .take_while(|exec| start.is_none_or(|start| exec.time() <= start))
+                    .count();
+                self.env_after(parent, forked_from)
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Children of every node, to iterate them in another order than they were added in
+pub type ChildLists = Rc<Vec<Vec<NodeId>>>;
+
 pub struct TreeIter<'a, T: 'a> {
     tree: &'a Tree<T>,
     frontier: Vec<Vec<NodeId>>,
+    children: Option<ChildLists>,
 }
 
 impl<'a, T: 'a> TreeIter<'a, T> {
-    fn new(tree: &'a Tree<T>) -> Self {
+    fn new(tree: &'a Tree<T>, root: NodeId) -> Self {
         TreeIter {
             tree,
-            frontier: vec![vec![0]],
+            frontier: vec![vec![root]],
+            children: None,
         }
     }
+
+    /// Yields children in the order of `children`
+    pub fn with_children(mut self, children: Option<ChildLists>) -> Self {
+        self.children = children;
+        self
+    }
 }
 
 impl<'a, T> Iterator for TreeIter<'a, T> {
@@ -108,8 +160,12 @@ impl<'a, T> Iterator for TreeIter<'a, T> {
         let path = self.frontier.pop()?;
 
         {
-            let node = &self.tree.get(*path.last().unwrap());
-            for child_idx in node.children.iter().rev() {
+            let id = *path.last().unwrap();
+            let children = match &self.children {
+                Some(children) => &children[id],
+                None => self.tree.get(id).children(),
+            };
+            for child_idx in children.iter().rev() {
                 let mut p = path.clone();
                 p.push(*child_idx);
                 self.frontier.push(p);
@@ -120,21 +176,429 @@ impl<'a, T> Iterator for TreeIter<'a, T> {
     }
 }
 
-impl<'a> TVTree for &'a Tree<ProcessData> {
+/// Process tree as shown in the tree view, optionally with sorted siblings
+pub struct OrderedTree<'a> {
+    tree: &'a ProcessTree,
+    /// Sorted children of every node, `None` keeps the order they were added in
+    children: Option<ChildLists>,
+    /// Index of every node among its siblings
+    positions: Vec<usize>,
+}
+
+impl<'a> OrderedTree<'a> {
+    pub fn new(tree: &'a ProcessTree) -> Self {
+        let mut ordered = OrderedTree {
+            tree,
+            children: None,
+            positions: Vec::new(),
+        };
+        ordered.update_positions();
+        ordered
+    }
+
+    fn get(&self, id: NodeId) -> &'a Node<ProcessData> {
+        self.tree.get(id)
+    }
+
+    fn children(&self, id: NodeId) -> &[NodeId] {
+        match &self.children {
+            Some(children) => &children[id],
+            None => self.get(id).children(),
+        }
+    }
+
+    fn update_positions(&mut self) {
+        let mut positions = vec![0; self.tree.num_nodes()];
+        for id in 0..self.tree.num_nodes() {
+            for (pos, &child) in self.children(id).iter().enumerate() {
+                positions[child] = pos;
+            }
+        }
+        self.positions = positions;
+    }
+}
+
+/// Compares known values by `descending`, unknown ones always come last
+fn known_first<T: Ord>(a: Option<T>, b: Option<T>, descending: bool) -> cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if descending => b.cmp(&a),
+        (Some(a), Some(b)) => a.cmp(&b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    }
+}
+
+/// Orders successful processes before failed ones and exits before signals
+fn status_rank(status: Option<process::ExitStatus>) -> Option<(u8, i32)> {
+    match status? {
+        process::ExitStatus::Exited(code) => Some((0, code)),
+        process::ExitStatus::Signaled(signal) => Some((1, signal)),
+    }
+}
+
+impl<'a> TVTree for OrderedTree<'a> {
     type NodeIter = TreeIter<'a, ProcessData>;
-    type LineIter = ProcessDataLineIter<'a>;
+    type LineIter = ProcessDataLineIter;
 
     fn size(&self) -> usize {
-        self.num_nodes()
+        self.tree.num_nodes()
     }
     fn next_sibling(&self, node: usize) -> Option<usize> {
-        Tree::next_sibling(self, node)
+        let parent = self.get(node).parent()?;
+        self.children(parent).get(self.positions[node] + 1).cloned()
     }
 
     fn node_iter(&self) -> Self::NodeIter {
-        self.iter()
+        self.tree.iter().with_children(self.children.clone())
     }
     fn line_iter(&self, node: usize) -> Self::LineIter {
         ProcessDataLineIter::new(self.get(node).data())
     }
+
+    fn set_sort(&mut self, sort: Option<Sort>) {
+        let tree = self.tree;
+        self.children = sort.map(|Sort { key, descending }| {
+            let mut sizes = vec![1; tree.num_nodes()];
+            if key == SortKey::Size {
+                for path in tree.iter() {
+                    for &id in &path[..path.len() - 1] {
+                        sizes[id] += 1;
+                    }
+                }
+            }
+            let order = |&a: &NodeId, &b: &NodeId| {
+                let data = |id| tree.get(id).data();
+                let (a_data, b_data) = (data(a), data(b));
+                match key {
+                    SortKey::Pid => known_first(Some(a_data.pid()), Some(b_data.pid()), descending),
+                    SortKey::Start => {
+                        known_first(a_data.start_time(), b_data.start_time(), descending)
+                    }
+                    SortKey::Duration => {
+                        known_first(a_data.duration(), b_data.duration(), descending)
+                    }
+                    SortKey::Size => known_first(Some(sizes[a]), Some(sizes[b]), descending),
+                    SortKey::Status => known_first(
+                        status_rank(a_data.exit_status()),
+                        status_rank(b_data.exit_status()),
+                        descending,
+                    ),
+                    SortKey::Name => {
+                        known_first(Some(a_data.program()), Some(b_data.program()), descending)
+                    }
+                }
+            };
+
+            let children = (0..tree.num_nodes())
+                .map(|id| {
+                    let mut children = tree.get(id).children().to_vec();
+                    children.sort_by(order);
+                    children
+                })
+                .collect();
+            Rc::new(children)
+        });
+        self.update_positions();
+    }
+
+    fn annotation(&self, node: usize) -> Option<String> {
+        let data = self.get(node).data();
+        let mut parts = Vec::new();
+        if let Some(status) = data.exit_status().filter(|s| !s.success()) {
+            parts.push(status.to_string());
+        }
+        if let Some(duration) = data.duration() {
+            parts.push(util::format_duration(duration));
+        }
+
+        if parts.is_empty() {
+            None
+        } else {
+            Some(format!("[{}]", parts.join(", ")))
+        }
+    }
+    fn failed(&self, node: usize) -> bool {
+        self.get(node)
+            .data()
+            .exit_status()
+            .is_some_and(|s| !s.success())
+    }
+    fn command(&self, node: usize) -> Option<String> {
+        let argv = self.get(node).data().argv()?;
+        let argv: Vec<_> = argv.iter().map(|arg| util::quote(arg)).collect();
+        Some(argv.join(" "))
+    }
+    fn describe(&self, node: usize) -> Option<String> {
+        let data = self.get(node).data();
+        Some(format!("pid {} {}", data.pid(), data.program()))
+    }
+    fn details(&self, node: usize) -> Vec<String> {
+        let data = self.get(node).data();
+        let parent = self.get(node).parent().map(|id| self.get(id).data());
+        let exec = data.execs().last();
+
+        let mut lines = Vec::new();
+        let mut field = |name: &str, value: String| lines.push(format!("{:<9} {}", name, value));
+
+        field("pid", data.pid().to_string());
+        if let Some(parent) = parent {
+            field("ppid", parent.pid().to_string());
+        }
+        if let Some(cwd) = exec.and_then(|e| e.cwd()) {
+            field("cwd", util::escape(cwd).into_owned());
+        }
+        if data.execs().len() > 1 {
+            field("execs", data.execs().len().to_string());
+        }
+        if let Some(status) = data.exit_status() {
+            field("status", status.to_string());
+        }
+
+        // Times relative to the start of the trace
+        let epoch = self.get(0).data().start_time().unwrap_or(0);
+        let relative = |t: u64| format!("+{}", util::format_duration(t.saturating_sub(epoch)));
+        if let Some(start) = data.start_time() {
+            field("start", relative(start));
+        }
+        if let Some(end) = data.end_time() {
+            field("end", relative(end));
+        }
+        if let Some(duration) = data.duration() {
+            field("duration", util::format_duration(duration));
+        }
+        if let Some(rusage) = data.rusage() {
+            field("user", util::format_duration(rusage.user_time));
+            field("system", util::format_duration(rusage.system_time));
+            field("max rss", format!("{} kB", rusage.max_rss));
+            field(
+                "faults",
+                format!(
+                    "{} minor, {} major",
+                    rusage.minor_faults, rusage.major_faults
+                ),
+            );
+        }
+
+        if let Some(argv) = data.argv() {
+            lines.push(String::new());
+            lines.push("argv".to_string());
+            lines.extend(argv.iter().map(|arg| format!("  {}", util::escape(arg))));
+        }
+
+        // Environment changes compared to the inherited environment
+        if let Some(changes) = exec.and_then(|e| e.env_changes()) {
+            lines.push(String::new());
+            lines.push(format!("env ({} changed)", changes.len()));
+            lines.extend(changes.iter().map(|var| format!("  {}", util::escape(var))));
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::export::testutil;
+    use crate::process::{ExecData, ExitStatus, Pid, Rusage};
+    use crate::tui::theme::{Color, Fg};
+    use crate::tui::tv::TreeView;
+
+    use termion::style;
+
+    /// Process started at 1000us which ran `cmdline` in /src and ended with `status`
+    fn exited(pid: Pid, cmdline: &str, duration: u64, status: ExitStatus) -> ProcessData {
+        let mut data = testutil::process(pid, "/src", &[cmdline], 1000, 1000 + duration);
+        data.set_exit_status(status);
+        data
+    }
+
+    #[test]
+    fn annotation() {
+        let mut t = ProcessTree::new(exited(0, "make all", 2_500_000, ExitStatus::Exited(2)));
+        t.insert(exited(0, "true", 1500, ExitStatus::Exited(0)), Some(0));
+        t.insert(exited(0, "cc1", 20, ExitStatus::Signaled(9)), Some(0));
+        t.insert(ProcessData::new(0), Some(0));
+
+        let t = OrderedTree::new(&t);
+        assert_eq!(t.annotation(0), Some("[exit 2, 2.50s]".to_string()));
+        assert_eq!(t.annotation(1), Some("[1ms]".to_string()));
+        assert_eq!(t.annotation(2), Some("[SIGKILL, 20us]".to_string()));
+        assert_eq!(t.annotation(3), None);
+        assert_eq!(
+            (0..4).map(|id| t.failed(id)).collect::<Vec<_>>(),
+            vec![true, false, true, false]
+        );
+    }
+
+    #[test]
+    fn details() {
+        let mut root = exited(0, "make all", 2_500_000, ExitStatus::Exited(2));
+        root.execs
+            .last_mut()
+            .unwrap()
+            .set_env_changes(vec!["+PATH=/bin".to_string()]);
+        let mut t = ProcessTree::new(root);
+
+        let mut data = testutil::process(7, "/src", &["cc -c a.c"], 2000, 3500);
+        data.set_exit_status(ExitStatus::Exited(0));
+        data.set_rusage(Rusage {
+            user_time: 1000,
+            system_time: 200,
+            max_rss: 512,
+            minor_faults: 3,
+            major_faults: 0,
+        });
+        data.execs
+            .last_mut()
+            .unwrap()
+            .set_env_changes(vec!["+CC=cc".to_string()]);
+        let id = t.insert(data, Some(0));
+
+        assert_eq!(
+            OrderedTree::new(&t).details(id),
+            vec![
+                "pid       7",
+                "ppid      0",
+                "cwd       /src",
+                "status    exit 0",
+                "start     +1ms",
+                "end       +2ms",
+                "duration  1ms",
+                "user      1ms",
+                "system    200us",
+                "max rss   512 kB",
+                "faults    3 minor, 0 major",
+                "",
+                "argv",
+                "  cc",
+                "  -c",
+                "  a.c",
+                "",
+                "env (1 changed)",
+                "  +CC=cc",
+            ]
+        );
+        assert_eq!(OrderedTree::new(&t).describe(id), Some("pid 7 cc".to_string()));
+        assert_eq!(OrderedTree::new(&t).command(id), Some("cc -c a.c".to_string()));
+        assert_eq!(
+            OrderedTree::new(&t).details(0)[..3],
+            ["pid       0", "cwd       /src", "status    exit 2"]
+        );
+    }
+
+    #[test]
+    fn env_after() {
+        let exec = |time, changes: &[&str]| {
+            let mut exec = ExecData::new(time, vec!["sh".to_string()]);
+            exec.set_env_changes(changes.iter().map(|c| c.to_string()).collect());
+            exec
+        };
+        let mut root = ProcessData::new(1);
+        root.add_exec(exec(0, &["+PATH=/bin", "+HOME=/root"]));
+        root.add_exec(exec(20, &["+PATH=/usr/bin"]));
+        let mut t = ProcessTree::new(root);
+
+        // Forked before and after the second exec of the root
+        let mut early = ProcessData::new(2);
+        early.set_start_time(10);
+        early.add_exec(exec(30, &["-HOME", "+CC=cc"]));
+        let early = t.insert(early, Some(0));
+        let mut late = ProcessData::new(3);
+        late.set_start_time(40);
+        let late = t.insert(late, Some(0));
+
+        assert_eq!(t.env_after(0, 2), ["PATH=/usr/bin", "HOME=/root"]);
+        assert_eq!(t.env_after(early, 0), ["PATH=/bin", "HOME=/root"]);
+        assert_eq!(t.env_after(early, 1), ["PATH=/bin", "CC=cc"]);
+        assert_eq!(t.env_after(late, 0), ["PATH=/usr/bin", "HOME=/root"]);
+    }
+
+    #[test]
+    fn sort() {
+        let mut t = ProcessTree::new(exited(0, "make", 10_000, ExitStatus::Exited(2)));
+        for (pid, argv, duration, status) in &[
+            (30, "cc b.c", 3000, ExitStatus::Exited(0)),
+            (20, "ld", 1000, ExitStatus::Signaled(9)),
+            (40, "as", 2000, ExitStatus::Exited(1)),
+        ] {
+            t.insert(exited(*pid, argv, *duration, *status), Some(0));
+        }
+        t.insert(ProcessData::new(50), Some(1));
+
+        let mut ordered = OrderedTree::new(&t);
+        let children = |ordered: &OrderedTree| -> Vec<NodeId> {
+            ordered.node_iter().filter(|p| p.len() == 2).map(|p| p[1]).collect()
+        };
+        let mut sorted = |key, descending| {
+            ordered.set_sort(Some(Sort { key, descending }));
+            children(&ordered)
+        };
+        assert_eq!(sorted(SortKey::Pid, false), vec![2, 1, 3]);
+        assert_eq!(sorted(SortKey::Duration, true), vec![1, 3, 2]);
+        assert_eq!(sorted(SortKey::Status, false), vec![1, 3, 2]);
+        assert_eq!(sorted(SortKey::Name, false), vec![3, 1, 2]);
+        assert_eq!(sorted(SortKey::Size, true), vec![1, 2, 3]);
+        assert_eq!(ordered.next_sibling(1), Some(2));
+        assert_eq!(ordered.next_sibling(3), None);
+
+        // Unknown values come last in both directions
+        ordered.set_sort(Some(Sort {
+            key: SortKey::Duration,
+            descending: false,
+        }));
+        assert_eq!(ordered.node_iter().last(), Some(vec![0, 1, 4]));
+
+        // The tree itself keeps fork order
+        assert_eq!(t.get(0).children(), &[1, 2, 3]);
+        let mut tv = TreeView::new(OrderedTree::new(&t));
+        tv.set_sort(Some(Sort {
+            key: SortKey::Pid,
+            descending: false,
+        }));
+        let mut buf = Vec::new();
+        tv.write_lines(&mut buf, false, false).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            text.lines().collect::<Vec<_>>(),
+            vec![
+                "[+] make",
+                "    ├── [+] ld",
+                "    ├── [+] cc b.c",
+                "    │       └── [+] UNKNOWN",
+                "    └── [+] as",
+            ]
+        );
+    }
+
+    #[test]
+    fn write_lines() {
+        let mut t = ProcessTree::new(exited(0, "make all", 2_500_000, ExitStatus::Exited(2)));
+        t.insert(exited(0, "true", 1500, ExitStatus::Exited(0)), Some(0));
+
+        let mut tv = TreeView::new(OrderedTree::new(&t));
+        let mut buf = Vec::new();
+        tv.write_lines(&mut buf, false, true).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "[+] make all  [exit 2, 2.50s]\n    └── [+] true  [1ms]\n"
+        );
+
+        let mut buf = Vec::new();
+        tv.write_lines(&mut buf, false, false).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "[+] make all\n    └── [+] true\n"
+        );
+
+        let mut buf = Vec::new();
+        tv.write_lines(&mut buf, true, true).unwrap();
+        let colored = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = colored.lines().collect();
+        // Failed nodes are red, program names bold and guides grey
+        assert!(lines[0].starts_with(&format!("{}[+] {}", Fg(Color::Basic(8)), style::Reset)));
+        assert!(lines[0].contains(&format!("{}\x1b[1mmake{}", Fg(Color::Basic(1)), style::Reset)));
+        assert!(lines[1].contains(&format!("{}\x1b[1mtrue{}", style::Reset, style::Reset)));
+        assert!(lines[1].ends_with(&format!("{}  [1ms]{}", Fg(Color::Basic(8)), style::Reset)));
+    }
 }
@@ -1,3 +1,4 @@
+use nix::errno::Errno;
 use nix::sys::{ptrace, signal, wait};
 use nix::unistd;
 use nix::unistd::Pid;
@@ -5,12 +6,14 @@ use nix::unistd::Pid;
 use failure::ResultExt;
 
 use crate::process::tree::{NodeId, ProcessTree};
-use crate::process::ProcessData;
+use crate::process::{self, ExitStatus, ProcessData};
+use crate::server::{Event, Server};
 use crate::Result;
 
 use std::collections::HashMap;
 use std::ffi;
-use std::process;
+use std::path;
+use std::rc::Rc;
 
 macro_rules! debug {
     ($($arg:tt)+) => ({
@@ -27,15 +30,12 @@ fn handle_nix_error(e: nix::Error) -> ! {
 fn child(program: &ffi::CString, args: &[ffi::CString]) -> ! {
     if let Err(e) = ptrace::traceme() {
         eprintln!("traceme(): {}", e);
-        process::exit(-1);
+        std::process::exit(-1);
     }
 
-    if let Err(e) = unistd::execvp(&program, &args) {
-        eprintln!("execvp(): {}", e);
-        process::exit(-1);
-    }
-
-    unreachable!();
+    let Err(e) = unistd::execvp(program, args);
+    eprintln!("execvp(): {}", e);
+    std::process::exit(-1);
 }
 
 fn int_to_ptrace_event(i: i32) -> ptrace::Event {
@@ -57,14 +57,21 @@ fn int_to_ptrace_event(i: i32) -> ptrace::Event {
 pub struct Race {
     pt: ProcessTree,
     pid_map: HashMap<Pid, NodeId>,
+    /// Environment of the current image of every process by node, shared with forked children
+    /// until they exec
+    envs: Vec<Rc<Vec<String>>>,
+    server: Option<Server>,
 }
 
 impl Race {
     fn new(pid: Pid) -> Self {
-        let root = ProcessData::new(pid.as_raw());
+        let mut root = ProcessData::new(pid.as_raw());
+        root.set_start_time(process::timestamp());
         let mut race = Race {
             pt: ProcessTree::new(root),
             pid_map: HashMap::new(),
+            envs: vec![Rc::new(Vec::new())],
+            server: None,
         };
         race.pid_map.insert(pid, 0);
         race
@@ -86,10 +93,26 @@ impl Race {
         }
     }
 
+    /// Streams events to clients of a unix domain socket at `path` while tracing
+    pub fn listen<P: AsRef<path::Path>>(&mut self, path: P) -> Result<()> {
+        self.server = Some(Server::bind(path)?);
+        Ok(())
+    }
+
     pub fn trace(&mut self) {
-        while let Ok(result) = wait::waitpid(Pid::from_raw(-1), Some(wait::WaitPidFlag::__WALL)) {
-            self.handle_wakeup(result);
+        loop {
+            let result = wait::waitpid(Pid::from_raw(-1), Some(wait::WaitPidFlag::__WALL));
+            if let Some(server) = self.server.as_mut() {
+                server.accept(&self.pt);
+            }
+            match result {
+                Ok(result) => self.handle_wakeup(result),
+                // Woken by the server to subscribe new clients
+                Err(nix::Error::Sys(Errno::EINTR)) => (),
+                Err(_) => break,
+            }
         }
+        self.notify(&Event::Done);
     }
 
     pub fn into_tree(self) -> ProcessTree {
@@ -104,8 +127,10 @@ impl Race {
 
         #[allow(unused_variables)]
         match res {
-            Exited(pid, status) => (),
-            Signaled(pid, sig, has_coredump) => (),
+            Exited(pid, status) => self.handle_exit(pid, ExitStatus::Exited(status)),
+            Signaled(pid, sig, has_coredump) => {
+                self.handle_exit(pid, ExitStatus::Signaled(sig as i32))
+            }
             Stopped(pid, sig) => {
                 match sig {
                     SIGTRAP => {
@@ -113,15 +138,14 @@ impl Race {
                         assert!(self.pid_map.contains_key(&pid));
 
                         self.setopts(pid);
-                        self.read_cmdline(pid);
+                        self.read_exec(pid);
                         Race::cont(pid, None);
                     }
                     SIGSTOP => {
                         // Expected once per tracee on start
                         self.setopts(pid);
                         if !self.pid_map.contains_key(&pid) {
-                            let id = self.pt.insert(ProcessData::new(pid.as_raw()), None);
-                            self.pid_map.insert(pid, id);
+                            self.insert_process(pid, None);
                         }
                         self.read_cmdline(pid);
                         Race::cont(pid, None);
@@ -159,25 +183,77 @@ impl Race {
             PTRACE_EVENT_FORK | PTRACE_EVENT_VFORK | PTRACE_EVENT_CLONE => {
                 let child_pid = Pid::from_raw(ev_msg as i32);
                 if !self.pid_map.contains_key(&child_pid) {
-                    let id = self.pt.insert(
-                        ProcessData::new(child_pid.as_raw()),
-                        Some(self.pid_map[&pid]),
-                    );
-                    self.pid_map.insert(child_pid, id);
+                    self.insert_process(child_pid, Some(self.pid_map[&pid]));
                 } else {
-                    self.pt
-                        .set_parent(self.pid_map[&child_pid], self.pid_map[&pid]);
+                    let (id, parent) = (self.pid_map[&child_pid], self.pid_map[&pid]);
+                    self.set_parent(id, parent);
+                    self.notify(&Event::Parent { id, parent });
                 }
             }
             PTRACE_EVENT_EXEC => {
-                self.read_cmdline(pid);
+                self.read_exec(pid);
             }
             PTRACE_EVENT_VFORK_DONE => (),
-            PTRACE_EVENT_EXIT => (),
+            PTRACE_EVENT_EXIT => {
+                let data = self.pt.get_mut(self.pid_map[&pid]).data_mut();
+                if let Err(e) = data.read_stat() {
+                    debug!("Reading stat of {} failed: {}", pid, e);
+                }
+            }
             PTRACE_EVENT_SECCOMP => (),
         }
     }
 
+    fn insert_process(&mut self, pid: Pid, parent: Option<NodeId>) {
+        let time = process::timestamp();
+        let mut data = ProcessData::new(pid.as_raw());
+        data.set_start_time(time);
+
+        let id = self.pt.insert(data, parent);
+        self.pid_map.insert(pid, id);
+        let env = match parent {
+            Some(parent) => Rc::clone(&self.envs[parent]),
+            None => Rc::new(Vec::new()),
+        };
+        self.envs.push(env);
+        self.notify(&Event::Spawn {
+            id,
+            parent,
+            pid: pid.as_raw(),
+            time,
+        });
+    }
+
+    fn set_parent(&mut self, id: NodeId, parent: NodeId) {
+        self.pt.set_parent(id, parent);
+
+        // An exec seen before the fork event recorded its environment relative to nothing
+        let data = self.pt.get_mut(id).data_mut();
+        match data.execs_mut().first_mut() {
+            Some(exec) if exec.env_changes().is_some() => {
+                let env = process::apply_env_diff(&[] as &[&str], exec.env_changes().unwrap());
+                exec.set_env_changes(process::env_diff(&self.envs[parent], &env));
+            }
+            _ => self.envs[id] = Rc::clone(&self.envs[parent]),
+        }
+    }
+
+    fn handle_exit(&mut self, pid: Pid, status: ExitStatus) {
+        if let Some(&id) = self.pid_map.get(&pid) {
+            let time = process::timestamp();
+            let data = self.pt.get_mut(id).data_mut();
+            data.set_end_time(time);
+            data.set_exit_status(status);
+            self.notify(&Event::Exit { id, time, status });
+        }
+    }
+
+    fn notify(&mut self, event: &Event) {
+        if let Some(server) = self.server.as_mut() {
+            server.send(event);
+        }
+    }
+
     fn setopts(&self, pid: Pid) {
         use self::ptrace::Options;
 
@@ -185,6 +261,7 @@ impl Race {
             | Options::PTRACE_O_TRACEEXEC
             | Options::PTRACE_O_TRACEFORK
             | Options::PTRACE_O_TRACEVFORK
+            | Options::PTRACE_O_TRACEEXIT
             | Options::PTRACE_O_TRACESYSGOOD
             | Options::PTRACE_O_EXITKILL;
 
@@ -212,4 +289,25 @@ impl Race {
             .read_cmdline()
             .unwrap();
     }
+
+    fn read_exec(&mut self, pid: Pid) {
+        assert!(self.pid_map.contains_key(&pid));
+
+        let id = self.pid_map[&pid];
+        let data = self.pt.get_mut(id).data_mut();
+        data.read_exec(process::timestamp(), &self.envs[id]).unwrap();
+        if let Some(changes) = data.execs().last().unwrap().env_changes() {
+            self.envs[id] = Rc::new(process::apply_env_diff(&self.envs[id], changes));
+        }
+
+        if let Some(server) = self.server.as_mut() {
+            let exec = data.execs().last().unwrap();
+            server.send(&Event::Exec {
+                id,
+                time: exec.time(),
+                argv: exec.argv(),
+                cwd: exec.cwd(),
+            });
+        }
+    }
 }
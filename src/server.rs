@@ -0,0 +1,331 @@
+use serde_derive::Serialize;
+
+use crate::process::tree::{NodeId, ProcessTree};
+use crate::process::{ExitStatus, Pid, Timestamp};
+use crate::Result;
+
+use nix::sys::signal;
+
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time;
+
+/// Time a new client has to send its request line before it is subscribed without snapshot
+const REQUEST_TIMEOUT: time::Duration = time::Duration::from_millis(200);
+/// Lets a writer thread give up on a client which stopped reading
+const WRITE_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+/// Events queued per client, a client falling further behind is dropped
+const QUEUE_LEN: usize = 4096;
+/// Interrupts the tracer's wait so it subscribes new clients without waiting for the tracees
+const WAKE_SIGNAL: signal::Signal = signal::Signal::SIGUSR1;
+/// Time after which the tracer is woken again if a new client is still not subscribed
+const WAKE_INTERVAL: time::Duration = time::Duration::from_millis(10);
+
+extern "C" fn handle_wake(_: libc::c_int) {}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    Snapshot {
+        tree: &'a ProcessTree,
+    },
+    Spawn {
+        id: NodeId,
+        parent: Option<NodeId>,
+        pid: Pid,
+        time: Timestamp,
+    },
+    Parent {
+        id: NodeId,
+        parent: NodeId,
+    },
+    Exec {
+        id: NodeId,
+        time: Timestamp,
+        argv: &'a [String],
+        cwd: Option<&'a str>,
+    },
+    Exit {
+        id: NodeId,
+        time: Timestamp,
+        status: ExitStatus,
+    },
+    Done,
+}
+
+/// Streams tracer events as JSON lines to clients connected to a unix domain socket
+///
+/// A client sending the line `snapshot` right after connecting first receives the whole
+/// current tree as a `snapshot` event, then the events following it.
+///
+/// Connections are accepted on their own threads and handed over to the tracer, which is
+/// woken by `WAKE_SIGNAL` to subscribe them. Each client has its own writer thread fed by a
+/// bounded queue, so a slow reader never stalls the tracer but is dropped once it is full.
+#[derive(Debug)]
+pub struct Server {
+    path: path::PathBuf,
+    new_clients: mpsc::Receiver<(UnixStream, bool)>,
+    /// Clients handed over but not yet subscribed
+    pending: Arc<AtomicUsize>,
+    /// Set once the server is dropped, the tracer thread must not be signalled any more
+    closed: Arc<Mutex<bool>>,
+    clients: Vec<Client>,
+}
+
+#[derive(Debug)]
+struct Client {
+    queue: mpsc::SyncSender<Arc<str>>,
+    writer: thread::JoinHandle<()>,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        *self.closed.lock().unwrap() = true;
+        let _ = fs::remove_file(&self.path);
+
+        // Let the writers send what is queued before the process may exit
+        for Client { queue, writer } in self.clients.drain(..) {
+            drop(queue);
+            let _ = writer.join();
+        }
+    }
+}
+
+impl Server {
+    /// Listens at `path`, the calling thread is the one subscribing clients in `accept`
+    pub fn bind<P: AsRef<path::Path>>(path: P) -> Result<Self> {
+        let listener = UnixListener::bind(&path)?;
+
+        // Interrupts blocking calls of the tracer instead of restarting them
+        let action = signal::SigAction::new(
+            signal::SigHandler::Handler(handle_wake),
+            signal::SaFlags::empty(),
+            signal::SigSet::empty(),
+        );
+        unsafe {
+            signal::sigaction(WAKE_SIGNAL, &action)?;
+        }
+        let tracer = unsafe { libc::pthread_self() };
+
+        // Spawn acceptor, which reads the request of each client in its own thread
+        let (tx, new_clients) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let closed = Arc::new(Mutex::new(false));
+        let (acceptor_pending, acceptor_closed) = (Arc::clone(&pending), Arc::clone(&closed));
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let (tx, pending, closed) = (
+                    tx.clone(),
+                    Arc::clone(&acceptor_pending),
+                    Arc::clone(&acceptor_closed),
+                );
+                thread::spawn(move || {
+                    let snapshot = read_request(&stream).is_ok_and(|r| r.trim() == "snapshot");
+                    pending.fetch_add(1, atomic::Ordering::SeqCst);
+                    if tx.send((stream, snapshot)).is_err() {
+                        return;
+                    }
+                    // A wakeup just before the tracer starts waiting is lost, so repeat it
+                    while pending.load(atomic::Ordering::SeqCst) > 0 {
+                        let closed = closed.lock().unwrap();
+                        if *closed {
+                            return;
+                        }
+                        unsafe {
+                            libc::pthread_kill(tracer, WAKE_SIGNAL as libc::c_int);
+                        }
+                        drop(closed);
+                        thread::sleep(WAKE_INTERVAL);
+                    }
+                });
+            }
+        });
+
+        Ok(Server {
+            path: path.as_ref().to_path_buf(),
+            new_clients,
+            pending,
+            closed,
+            clients: Vec::new(),
+        })
+    }
+
+    /// Subscribes clients that connected in the meantime, sending snapshots where requested
+    pub fn accept(&mut self, tree: &ProcessTree) {
+        while let Ok((stream, snapshot)) = self.new_clients.try_recv() {
+            self.pending.fetch_sub(1, atomic::Ordering::SeqCst);
+            if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+                continue;
+            }
+
+            let (queue, lines) = mpsc::sync_channel(QUEUE_LEN);
+            if snapshot {
+                match event_line(&Event::Snapshot { tree }) {
+                    Some(line) => queue.try_send(line).unwrap(),
+                    None => continue,
+                }
+            }
+            let writer = thread::spawn(move || write_events(stream, lines));
+            self.clients.push(Client { queue, writer });
+        }
+    }
+
+    /// Queues `event` for all clients, dropping those that are gone or too far behind
+    pub fn send(&mut self, event: &Event) {
+        if let Some(line) = event_line(event) {
+            self.clients
+                .retain(|client| client.queue.try_send(Arc::clone(&line)).is_ok());
+        }
+    }
+}
+
+fn read_request(stream: &UnixStream) -> io::Result<String> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = String::new();
+    io::BufReader::new(stream).read_line(&mut request)?;
+    Ok(request)
+}
+
+/// JSON line of `event`, serialized once for all clients
+fn event_line(event: &Event) -> Option<Arc<str>> {
+    let mut line = serde_json::to_string(event).ok()?;
+    line.push('\n');
+    Some(line.into())
+}
+
+/// Writes queued lines until the server drops the queue or the client cannot be written to
+fn write_events(mut stream: UnixStream, lines: mpsc::Receiver<Arc<str>>) {
+    for line in lines {
+        if stream.write_all(line.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::process::ProcessData;
+
+    use std::env;
+    use std::io::Read;
+    use std::process;
+
+    fn socket_path(name: &str) -> path::PathBuf {
+        env::temp_dir().join(format!("race-test-{}-{}.sock", name, process::id()))
+    }
+
+    fn accept_all(server: &mut Server, tree: &ProcessTree, n: usize) {
+        while server.clients.len() < n {
+            server.accept(tree);
+            thread::sleep(time::Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn stream_events() {
+        let path = socket_path("stream");
+        let tree = ProcessTree::new(ProcessData::new(42));
+        let mut server = Server::bind(&path).unwrap();
+
+        let mut snapshot_client = UnixStream::connect(&path).unwrap();
+        snapshot_client.write_all(b"snapshot\n").unwrap();
+        let plain_client = UnixStream::connect(&path).unwrap();
+        accept_all(&mut server, &tree, 2);
+
+        server.send(&Event::Spawn {
+            id: 1,
+            parent: Some(0),
+            pid: 43,
+            time: 1000,
+        });
+        server.send(&Event::Exit {
+            id: 1,
+            time: 2000,
+            status: ExitStatus::Exited(1),
+        });
+        drop(server);
+        assert!(!path.exists());
+
+        let lines: Vec<serde_json::Value> = io::BufReader::new(snapshot_client)
+            .lines()
+            .map(|l| serde_json::from_str(&l.unwrap()).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["event"], "snapshot");
+        assert_eq!(lines[0]["tree"]["nodes"][0]["data"]["pid"], 42);
+        assert_eq!(
+            lines[1],
+            serde_json::json!({"event": "spawn", "id": 1, "parent": 0, "pid": 43, "time": 1000})
+        );
+        assert_eq!(
+            lines[2],
+            serde_json::json!({"event": "exit", "id": 1, "time": 2000, "status": {"Exited": 1}})
+        );
+
+        let mut plain = String::new();
+        io::BufReader::new(plain_client)
+            .read_to_string(&mut plain)
+            .unwrap();
+        assert_eq!(plain.lines().count(), 2);
+        assert!(plain.starts_with("{\"event\":\"spawn\""));
+    }
+
+    #[test]
+    fn drop_disconnected() {
+        let path = socket_path("drop");
+        let tree = ProcessTree::new(ProcessData::new(42));
+        let mut server = Server::bind(&path).unwrap();
+
+        let client = UnixStream::connect(&path).unwrap();
+        accept_all(&mut server, &tree, 1);
+        drop(client);
+
+        // The writer thread notices the closed socket on its first write
+        for _ in 0..100 {
+            server.send(&Event::Done);
+            if server.clients.is_empty() {
+                break;
+            }
+            thread::sleep(time::Duration::from_millis(10));
+        }
+        assert!(server.clients.is_empty());
+    }
+
+    #[test]
+    fn drop_slow() {
+        let path = socket_path("slow");
+        let tree = ProcessTree::new(ProcessData::new(42));
+        let mut server = Server::bind(&path).unwrap();
+
+        // Connected, but never reading
+        let _client = UnixStream::connect(&path).unwrap();
+        accept_all(&mut server, &tree, 1);
+
+        let argv = vec!["x".repeat(1000)];
+        let event = Event::Exec {
+            id: 1,
+            time: 1000,
+            argv: &argv,
+            cwd: None,
+        };
+        let mut sent = 0;
+        while !server.clients.is_empty() && sent < 2 * QUEUE_LEN {
+            server.send(&event);
+            sent += 1;
+        }
+        assert!(server.clients.is_empty());
+        assert!(sent > QUEUE_LEN);
+    }
+}
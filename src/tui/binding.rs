@@ -0,0 +1,123 @@
+use termion::event::Key;
+
+/// Entry of a key binding table, which both dispatches input and generates the help overlay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding<A> {
+    pub key: Key,
+    pub action: A,
+    pub help: &'static str,
+}
+
+/// Bindings listed under a common title in the help overlay
+pub type HelpSection = (&'static str, Vec<(Key, &'static str)>);
+
+pub fn lookup<A: Copy>(bindings: &[Binding<A>], key: Key) -> Option<A> {
+    bindings.iter().find(|b| b.key == key).map(|b| b.action)
+}
+
+pub fn help<A>(bindings: &[Binding<A>]) -> Vec<(Key, &'static str)> {
+    bindings.iter().map(|b| (b.key, b.help)).collect()
+}
+
+pub fn key_name(key: Key) -> String {
+    use termion::event::Key::*;
+
+    match key {
+        Char(' ') => "space".to_string(),
+        Char('\t') => "tab".to_string(),
+        Char('\n') => "enter".to_string(),
+        Char(c) => c.to_string(),
+        Ctrl(c) => format!("ctrl-{}", c),
+        Alt(c) => format!("alt-{}", c),
+        F(n) => format!("f{}", n),
+        Left => "left".to_string(),
+        Right => "right".to_string(),
+        Up => "up".to_string(),
+        Down => "down".to_string(),
+        Home => "home".to_string(),
+        End => "end".to_string(),
+        PageUp => "pgup".to_string(),
+        PageDown => "pgdn".to_string(),
+        Backspace => "backspace".to_string(),
+        Delete => "del".to_string(),
+        Insert => "ins".to_string(),
+        Esc => "esc".to_string(),
+        _ => "?".to_string(),
+    }
+}
+
+/// Formats help sections as lines, merging keys with the same description
+pub fn help_lines(sections: &[HelpSection]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (title, bindings) in sections {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.push(title.to_string());
+
+        let mut merged: Vec<(Vec<String>, &str)> = Vec::new();
+        for &(key, help) in bindings {
+            match merged.iter_mut().find(|(_, h)| *h == help) {
+                Some((keys, _)) => keys.push(key_name(key)),
+                None => merged.push((vec![key_name(key)], help)),
+            }
+        }
+        let merged: Vec<(String, &str)> = merged
+            .into_iter()
+            .map(|(keys, help)| (keys.join(", "), help))
+            .collect();
+        let width = merged
+            .iter()
+            .map(|(k, _)| k.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (keys, help) in merged {
+            lines.push(format!("  {:2$}  {}", keys, help, width));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings() {
+        let table = [
+            Binding {
+                key: Key::Char('j'),
+                action: 1,
+                help: "Down",
+            },
+            Binding {
+                key: Key::Down,
+                action: 1,
+                help: "Down",
+            },
+            Binding {
+                key: Key::Char(' '),
+                action: 2,
+                help: "Toggle",
+            },
+        ];
+        assert_eq!(lookup(&table, Key::Down), Some(1));
+        assert_eq!(lookup(&table, Key::Char('x')), None);
+
+        let sections = [
+            ("Tree", help(&table)),
+            ("General", vec![(Key::Char('q'), "Quit")]),
+        ];
+        assert_eq!(
+            help_lines(&sections),
+            vec![
+                "Tree",
+                "  j, down  Down",
+                "  space    Toggle",
+                "",
+                "General",
+                "  q  Quit",
+            ]
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use crate::tui;
+use crate::tui::widget::{Border, TextView};
+use crate::util::Rect;
+
+use termion::event::Key;
+
+/// Shows what was recorded about one node, next to the tree
+#[derive(Debug, Default)]
+pub struct DetailPane {
+    node: Option<usize>,
+    text: TextView,
+    rect: Option<Rect>,
+    focused: bool,
+    dirty: bool,
+}
+
+impl DetailPane {
+    pub fn new() -> Self {
+        DetailPane::default()
+    }
+
+    pub fn node(&self) -> Option<usize> {
+        self.node
+    }
+
+    pub fn set_lines(&mut self, node: usize, lines: Vec<String>) {
+        self.node = Some(node);
+        self.text.set_lines(lines);
+        self.dirty = true;
+    }
+}
+
+impl tui::Pane for DetailPane {
+    /// Takes the scrolling keys, the tree handles all others
+    fn handle_key(&mut self, key: Key) -> bool {
+        if !self.text.handle_key(key) {
+            return false;
+        }
+        self.dirty = true;
+        true
+    }
+
+    fn handle_mouse(&mut self, ev: tui::MouseEvent) {
+        if self.text.handle_mouse(ev) {
+            self.dirty = true;
+        }
+    }
+
+    fn rect(&self) -> Option<Rect> {
+        self.rect
+    }
+
+    fn set_focused(&mut self, focused: bool) {
+        if focused != self.focused {
+            self.focused = focused;
+            self.dirty = true;
+        }
+    }
+}
+
+impl tui::Draw for DetailPane {
+    fn draw(&mut self, rect: &Rect, frame: &mut tui::Frame) {
+        self.rect = Some(*rect);
+        frame.clear_rect(rect);
+
+        let mut border = Border::new(Some("Details".to_string()));
+        border.set_focused(self.focused);
+        let inner = border.draw(rect, frame);
+        self.text.draw(&inner, frame);
+
+        self.dirty = false;
+    }
+
+    fn dirty(&self) -> bool {
+        self.dirty
+    }
+}
@@ -0,0 +1,198 @@
+use crate::tui::{self, Client, Draw, Event, Frame, Tui};
+use crate::util::Point;
+use crate::Result;
+
+use termion::event::Key;
+
+use std::sync::mpsc;
+
+/// Backend keeping its frames in memory, driven by scripted input instead of a terminal
+pub struct Headless {
+    size: Point,
+    frame: Frame,
+    /// Text of every drawn frame, oldest first
+    screens: Vec<String>,
+    copied: Vec<String>,
+    channel: mpsc::SyncSender<Event>,
+}
+
+impl Headless {
+    /// Queues `keys` as input, `Tui::handle_pending` handles them
+    pub fn keys(&self, keys: &[Key]) {
+        for &key in keys {
+            self.send(Event::Input(termion::event::Event::Key(key)));
+        }
+    }
+
+    /// Queues one key per character of `text`
+    pub fn type_text(&self, text: &str) {
+        let keys: Vec<Key> = text.chars().map(Key::Char).collect();
+        self.keys(&keys);
+    }
+
+    /// Changes the size of the screen like a terminal window being resized
+    pub fn resize(&mut self, size: Point) {
+        self.size = size;
+        self.send(Event::TermResized);
+    }
+
+    pub fn send(&self, ev: Event) {
+        self.channel.send(ev).unwrap();
+    }
+
+    pub fn screens(&self) -> &[String] {
+        &self.screens
+    }
+
+    /// Texts passed to `Backend::copy`
+    pub fn copied(&self) -> &[String] {
+        &self.copied
+    }
+}
+
+/// Lines of the frame without trailing blanks
+fn screen_text(frame: &Frame) -> String {
+    (0..frame.size.y)
+        .map(|y| {
+            let line: String = frame.line(y).iter().map(|c| c.symbol.as_str()).collect();
+            line.trim_end().to_string() + "\n"
+        })
+        .collect()
+}
+
+impl tui::Backend for Headless {
+    fn new(channel: mpsc::SyncSender<Event>) -> Result<Self> {
+        let size = Point::new(40, 8);
+        Ok(Headless {
+            size,
+            frame: Frame::new(size),
+            screens: Vec::new(),
+            copied: Vec::new(),
+            channel,
+        })
+    }
+
+    fn draw(&mut self, _force: bool) {
+        self.screens.push(screen_text(&self.frame));
+    }
+
+    fn update_size(&mut self) -> Point {
+        self.frame = Frame::new(self.size);
+        self.size
+    }
+
+    fn copy(&mut self, text: &str) {
+        self.copied.push(text.to_string());
+    }
+
+    /// There is no terminal to give back and the tests keep running
+    fn suspend(&mut self) {}
+
+    fn resume(&mut self) {}
+
+    fn get_frame_mut(&mut self) -> &mut Frame {
+        &mut self.frame
+    }
+}
+
+impl<C: Client + Draw> Tui<C, Headless> {
+    pub fn backend(&self) -> &Headless {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut Headless {
+        &mut self.backend
+    }
+
+    /// Handles all queued events, returns false once the TUI quit
+    pub fn handle_pending(&mut self) -> bool {
+        while let Ok(ev) = self.evq.try_recv() {
+            if !self.handle_event(&ev) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Text of the last drawn frame
+    pub fn screen(&self) -> &str {
+        self.backend.screens.last().map_or("", String::as_str)
+    }
+
+    /// Types `text`, handles it and returns the screen afterwards
+    pub fn press(&mut self, text: &str) -> &str {
+        self.backend.type_text(text);
+        self.handle_pending();
+        self.screen()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tui::theme;
+
+    struct Lines {
+        lines: Vec<String>,
+        dirty: bool,
+    }
+
+    impl Client for Lines {
+        fn handle_key(&mut self, key: Key) -> tui::Response {
+            match key {
+                Key::Char('a') => {
+                    self.lines.push(format!("line {}", self.lines.len()));
+                    self.dirty = true;
+                    tui::Response::None
+                }
+                Key::Char('y') => tui::Response::Yank(self.lines.join("\n")),
+                _ => tui::Response::Message(format!("No binding for {:?}", key)),
+            }
+        }
+
+        fn handle_prompt(&mut self, _input: &str, _ev: tui::PromptEvent) -> tui::Response {
+            tui::Response::None
+        }
+    }
+
+    impl Draw for Lines {
+        fn draw(&mut self, rect: &tui::Rect, frame: &mut Frame) {
+            frame.clear_rect(rect);
+            for (y, l) in self.lines.iter().enumerate() {
+                frame.print(rect.min + Point::new(0, y as i32), l, theme::Style::new());
+            }
+            self.dirty = false;
+        }
+
+        fn dirty(&self) -> bool {
+            self.dirty
+        }
+    }
+
+    #[test]
+    fn headless() {
+        let lines = Lines {
+            lines: vec!["line 0".to_string()],
+            dirty: false,
+        };
+        let mut tui: Tui<_, Headless> = Tui::new(lines, theme::NONE).unwrap();
+        assert_eq!(tui.screen(), "line 0\n\n\n\n\n\n\n\n");
+
+        tui.backend_mut().resize(Point::new(30, 6));
+        assert_eq!(tui.press("a"), "line 0\nline 1\n\n\n\n\n");
+        assert_eq!(tui.press("x"), "line 0\nline 1\n\n\n\nNo binding for Char('x')\n");
+
+        // Input only goes to the open help popup
+        let drawn = tui.backend().screens().len();
+        assert!(tui.press("?").contains("┌ Key bindings"));
+        assert_eq!(tui.press("q"), "line 0\nline 1\n\n\n\n\n");
+        assert_eq!(tui.backend().screens().len(), drawn + 2);
+
+        assert_eq!(tui.press("y"), "line 0\nline 1\n\n\n\nCopied to clipboard\n");
+        assert_eq!(tui.backend().copied(), ["line 0\nline 1"]);
+
+        tui.backend().type_text("aq");
+        assert!(!tui.handle_pending());
+    }
+}
@@ -0,0 +1,83 @@
+use crate::util::{Point, Rect};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Parts side by side
+    Horizontal,
+    /// Parts on top of each other
+    Vertical,
+}
+
+/// Extent of the first part of a split, in cells along the split direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Size {
+    Percent(i32),
+    /// Fixed size of the first part
+    First(i32),
+    /// Fixed size of the second part
+    Last(i32),
+}
+
+/// Divides a rect into two parts, so several `Draw` implementors can share one frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Split {
+    direction: Direction,
+    size: Size,
+}
+
+impl Split {
+    pub fn new(direction: Direction, size: Size) -> Self {
+        Split { direction, size }
+    }
+
+    /// Returns both parts, either of which may be empty if `rect` is too small
+    pub fn split(&self, rect: &Rect) -> (Rect, Rect) {
+        let extent = match self.direction {
+            Direction::Horizontal => rect.width(),
+            Direction::Vertical => rect.height(),
+        };
+        let first = match self.size {
+            Size::Percent(percent) => extent * percent / 100,
+            Size::First(n) => n,
+            Size::Last(n) => extent - n,
+        };
+        let first = first.clamp(0, extent);
+
+        match self.direction {
+            Direction::Horizontal => (
+                Rect::new(rect.min, Point::new(rect.min.x + first - 1, rect.max.y)),
+                Rect::new(Point::new(rect.min.x + first, rect.min.y), rect.max),
+            ),
+            Direction::Vertical => (
+                Rect::new(rect.min, Point::new(rect.max.x, rect.min.y + first - 1)),
+                Rect::new(Point::new(rect.min.x, rect.min.y + first), rect.max),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corners(rect: &Rect) -> (i32, i32, i32, i32) {
+        (rect.min.x, rect.min.y, rect.max.x, rect.max.y)
+    }
+
+    #[test]
+    fn split() {
+        let rect = Rect::new(Point::new(0, 0), Point::new(99, 24));
+
+        let (a, b) = Split::new(Direction::Horizontal, Size::Percent(60)).split(&rect);
+        assert_eq!(corners(&a), (0, 0, 59, 24));
+        assert_eq!(corners(&b), (60, 0, 99, 24));
+
+        let (a, b) = Split::new(Direction::Vertical, Size::Last(1)).split(&rect);
+        assert_eq!(corners(&a), (0, 0, 99, 23));
+        assert_eq!(corners(&b), (0, 24, 99, 24));
+
+        let (a, b) = Split::new(Direction::Vertical, Size::First(30)).split(&rect);
+        assert_eq!(corners(&a), (0, 0, 99, 24));
+        assert_eq!(b.points().count(), 0);
+    }
+}
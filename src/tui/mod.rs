@@ -1,45 +1,100 @@
+pub mod binding;
+pub mod detail;
+#[cfg(test)]
+pub mod headless;
+pub mod layout;
+mod search;
 pub mod term;
+pub mod text;
+pub mod theme;
+pub mod tree_style;
 pub mod tv;
+pub mod widget;
 
+use crate::tui::binding::{Binding, HelpSection};
+use crate::tui::layout::{Direction, Size, Split};
+use crate::tui::theme::{Attrs, Color, Style, Theme};
+use crate::tui::widget::{Popup, POPUP_CLOSE_BINDINGS, SCROLL_BINDINGS};
 use crate::util::{Point, Rect};
 use crate::Result;
 
+use std::fs;
+use std::path;
 use std::sync::mpsc;
 
-type AnsiColor = u8;
+/// Lines scrolled per mouse wheel step
+const WHEEL_LINES: i32 = 3;
 
-const DARK_GREY: AnsiColor = 234;
-const LIGHT_GREY: AnsiColor = 236;
-const WHITE: AnsiColor = 255;
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Event {
     Input(termion::event::Event),
     TermResized,
+    /// SIGINT or SIGTERM
+    Terminate,
+    /// SIGTSTP
+    Suspend,
+    /// SIGCONT, after being stopped by something else than `Backend::suspend`
+    Continued,
+}
+
+/// Mouse input at zero based screen coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseEvent {
+    Click(Point),
+    WheelUp(Point),
+    WheelDown(Point),
+}
+
+impl MouseEvent {
+    pub fn pos(self) -> Point {
+        match self {
+            MouseEvent::Click(p) | MouseEvent::WheelUp(p) | MouseEvent::WheelDown(p) => p,
+        }
+    }
+
+    fn from_termion(ev: termion::event::MouseEvent) -> Option<Self> {
+        use termion::event::MouseButton;
+
+        match ev {
+            termion::event::MouseEvent::Press(button, x, y) => {
+                let p = Point::new(i32::from(x) - 1, i32::from(y) - 1);
+                match button {
+                    MouseButton::Left => Some(MouseEvent::Click(p)),
+                    MouseButton::WheelUp => Some(MouseEvent::WheelUp(p)),
+                    MouseButton::WheelDown => Some(MouseEvent::WheelDown(p)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 struct Cell {
     pos: Point,
-    c: char,
-    fg: AnsiColor,
-    bg: AnsiColor,
+    /// Grapheme shown in the cell, empty if the glyph to the left covers it
+    symbol: String,
+    fg: Color,
+    bg: Color,
+    attrs: Attrs,
 }
 
 impl Cell {
-    fn new(pos: Point, c: char) -> Self {
+    fn new(pos: Point, symbol: &str) -> Self {
         Cell {
             pos,
-            c,
-            fg: WHITE,
-            bg: DARK_GREY,
+            symbol: symbol.to_string(),
+            fg: Color::Default,
+            bg: Color::Default,
+            attrs: Attrs::NONE,
         }
     }
 
-    fn clear(&mut self) {
-        self.c = ' ';
-        self.fg = WHITE;
-        self.bg = DARK_GREY;
+    fn set_style(&mut self, style: Style) {
+        self.fg = style.fg.unwrap_or(self.fg);
+        self.bg = style.bg.unwrap_or(self.bg);
+        self.attrs = self.attrs.union(style.attrs);
     }
 }
 
@@ -47,6 +102,9 @@ impl Cell {
 pub struct Frame {
     size: Point,
     cells: Vec<Cell>,
+    /// Cells outside of this rect are left untouched
+    clip: Rect,
+    theme: Theme,
 }
 
 impl Frame {
@@ -54,30 +112,136 @@ impl Frame {
         let mut cells = Vec::with_capacity((size.x * size.y) as usize);
         for y in 0i32..size.y {
             for x in 0i32..size.x {
-                cells.push(Cell::new(Point::new(x, y), ' '));
+                cells.push(Cell::new(Point::new(x, y), " "));
             }
         }
 
-        Frame { size, cells }
+        Frame {
+            size,
+            cells,
+            clip: Rect::new(Point::new(0, 0), size - Point::new(1, 1)),
+            theme: Theme::default(),
+        }
+    }
+
+    fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
     }
 
     fn clear_rect(&mut self, rect: &Rect) {
-        for p in rect.points() {
-            self.cell_mut(p).clear();
+        for p in rect.intersect(&self.clip).points() {
+            self.put(p, ' ', Style::new());
+        }
+    }
+
+    fn cell_mut(&mut self, pos: Point) -> Option<&mut Cell> {
+        if self.clip.contains(pos) {
+            Some(&mut self.cells[(pos.x + pos.y * self.size.x) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Cells of line `y`
+    fn line(&self, y: i32) -> &[Cell] {
+        &self.cells[(y * self.size.x) as usize..][..self.size.x as usize]
+    }
+
+    /// Writes `c` in the theme's text style with `style` drawn over it
+    fn put(&mut self, pos: Point, c: char, style: Style) {
+        self.print(pos, c.encode_utf8(&mut [0; 4]), style);
+    }
+
+    /// Writes the grapheme `symbol`, which takes `width` cells
+    fn put_symbol(&mut self, pos: Point, symbol: &str, width: usize, style: Style) {
+        let style = Style::new()
+            .fg(Color::Default)
+            .bg(Color::Default)
+            .patch(self.theme.text)
+            .patch(style);
+        // Half of a wide glyph cannot be drawn, show a blank instead
+        let (symbol, width) = if width > 1 && !self.clip.contains(pos + Point::new(1, 0)) {
+            (" ", 1)
+        } else {
+            (symbol, width)
+        };
+        if !self.clip.contains(pos) {
+            return;
+        }
+
+        for x in 0..width as i32 {
+            self.split_wide(pos + Point::new(x, 0));
+        }
+        for x in 0..width as i32 {
+            let cell = self.cell_mut(pos + Point::new(x, 0)).unwrap();
+            cell.symbol.clear();
+            if x == 0 {
+                cell.symbol.push_str(symbol);
+            }
+            cell.attrs = Attrs::NONE;
+            cell.set_style(style);
         }
     }
 
-    fn cell_mut(&mut self, pos: Point) -> &mut Cell {
-        &mut self.cells[(pos.x + pos.y * self.size.x) as usize]
+    /// Blanks the other half of a wide glyph covering `pos`, before `pos` is overwritten
+    fn split_wide(&mut self, pos: Point) {
+        let idx = (pos.x + pos.y * self.size.x) as usize;
+        let other = if self.cells[idx].symbol.is_empty() {
+            idx - 1
+        } else if pos.x + 1 < self.size.x && self.cells[idx + 1].symbol.is_empty() {
+            idx + 1
+        } else {
+            return;
+        };
+        self.cells[other].symbol = " ".to_string();
     }
 
-    fn cells(&self) -> impl Iterator<Item = &Cell> {
-        self.cells.iter()
+    /// Adds a zero width `mark` to the glyph left of `pos`
+    fn attach(&mut self, pos: Point, mark: &str) {
+        let mut pos = pos - Point::new(1, 0);
+        if pos.x > 0 && self.cell_mut(pos).is_some_and(|c| c.symbol.is_empty()) {
+            pos = pos - Point::new(1, 0);
+        }
+        if let Some(cell) = self.cell_mut(pos) {
+            cell.symbol.push_str(mark);
+        }
     }
 
-    fn add(&mut self, cell: Cell) {
-        let pos = cell.pos;
-        self.cells[(pos.x + pos.y * self.size.x) as usize] = cell;
+    /// Draws `style` over the cell at `pos`, keeping its character
+    fn set_style(&mut self, pos: Point, style: Style) {
+        if let Some(cell) = self.cell_mut(pos) {
+            cell.set_style(style);
+        }
+    }
+
+    /// Writes `text` starting at `pos`, returns the number of cells written
+    ///
+    /// Wide glyphs take two cells, control characters are replaced and never reach the
+    /// terminal.
+    fn print(&mut self, pos: Point, text: &str, style: Style) -> i32 {
+        let mut x = 0;
+        for (g, width) in text::graphemes(text) {
+            if width == 0 {
+                self.attach(pos + Point::new(x, 0), g);
+            } else {
+                self.put_symbol(pos + Point::new(x, 0), g, width, style);
+                x += width as i32;
+            }
+        }
+        x
+    }
+
+    /// Restricts all drawing done by `f` to `rect`
+    fn clipped<R, F: FnOnce(&mut Frame) -> R>(&mut self, rect: &Rect, f: F) -> R {
+        let clip = self.clip;
+        self.clip = clip.intersect(rect);
+        let result = f(self);
+        self.clip = clip;
+        result
     }
 }
 
@@ -89,6 +253,12 @@ where
     fn draw(&mut self, redraw: bool);
     fn update_size(&mut self) -> Point;
     fn get_frame_mut(&mut self) -> &mut Frame;
+    /// Asks the terminal to put `text` into the clipboard
+    fn copy(&mut self, text: &str);
+    /// Gives the terminal back to the shell and stops the process until it is continued
+    fn suspend(&mut self);
+    /// Takes the terminal again after the process was continued
+    fn resume(&mut self);
 }
 
 pub trait Draw {
@@ -96,8 +266,111 @@ pub trait Draw {
     fn dirty(&self) -> bool;
 }
 
+/// What the TUI should do after a client handled input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    None,
+    /// Opens the prompt line with the given prefix, input is passed to `Client::handle_prompt`
+    Prompt(String),
+    /// Shows a message in the prompt line until the next input
+    Message(String),
+    /// Opens a modal popup, which gets all input until it is closed
+    Popup(Popup),
+    /// Copies text to where `YankTarget` says
+    Yank(String),
+}
+
+/// Destination of text copied by clients
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum YankTarget {
+    /// System clipboard via the terminal's OSC 52 support
+    Clipboard,
+    /// Printed after the TUI exits, the last copied text wins
+    Stdout,
+    File(path::PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptEvent {
+    Changed,
+    Submitted,
+    Cancelled,
+}
+
 pub trait Client {
-    fn handle_char(&mut self, c: char);
+    /// Handles a key not bound by the TUI itself
+    fn handle_key(&mut self, key: termion::event::Key) -> Response;
+    fn handle_prompt(&mut self, input: &str, ev: PromptEvent) -> Response;
+
+    fn handle_mouse(&mut self, _ev: MouseEvent) -> Response {
+        Response::None
+    }
+
+    /// Key bindings listed in the help overlay, in front of the general ones
+    fn help(&self) -> Vec<HelpSection> {
+        Vec::new()
+    }
+
+    /// Panes of the client which can take the focus from it, in focus order
+    fn panes(&mut self) -> Vec<&mut dyn Pane> {
+        Vec::new()
+    }
+}
+
+/// Part of a client's screen with input of its own, like a detail pane next to a list
+///
+/// The TUI moves the focus between the client and its panes. Keys go to the focused pane
+/// first, mouse events to the pane under the pointer.
+pub trait Pane {
+    /// Handles a key while focused, returns whether it was consumed or goes on to the client
+    fn handle_key(&mut self, key: termion::event::Key) -> bool;
+    fn handle_mouse(&mut self, ev: MouseEvent);
+    /// Rect the pane was last drawn to
+    fn rect(&self) -> Option<Rect>;
+    /// Highlights the pane while it has the focus
+    fn set_focused(&mut self, focused: bool);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Quit,
+    Help,
+    Suspend,
+    FocusNext,
+}
+
+const BINDINGS: &[Binding<Action>] = &[
+    Binding {
+        key: termion::event::Key::Char('q'),
+        action: Action::Quit,
+        help: "Quit",
+    },
+    Binding {
+        key: termion::event::Key::Ctrl('c'),
+        action: Action::Quit,
+        help: "Quit",
+    },
+    Binding {
+        key: termion::event::Key::Char('?'),
+        action: Action::Help,
+        help: "Show this help",
+    },
+    Binding {
+        key: termion::event::Key::Ctrl('z'),
+        action: Action::Suspend,
+        help: "Suspend to the shell",
+    },
+    Binding {
+        key: termion::event::Key::Char('\t'),
+        action: Action::FocusNext,
+        help: "Focus next pane",
+    },
+];
+
+#[derive(Debug)]
+struct Prompt {
+    prefix: String,
+    input: String,
 }
 
 #[derive(Debug)]
@@ -110,6 +383,16 @@ where
     backend: B,
     evq: mpsc::Receiver<Event>,
 
+    prompt: Option<Prompt>,
+    message: Option<String>,
+    popup: Option<Popup>,
+    /// Index of the client's pane receiving keys first, the client itself if `None`
+    focus: Option<usize>,
+
+    theme: Theme,
+    yank: YankTarget,
+    /// Text to print after exiting, for `YankTarget::Stdout`
+    yanked: Option<String>,
     size: Point,
 }
 
@@ -118,13 +401,21 @@ where
     C: Client + Draw,
     B: Backend,
 {
-    pub fn new(client: C) -> Result<Self> {
+    pub fn new(client: C, theme: Theme) -> Result<Self> {
         let (tx, evq) = mpsc::sync_channel(100);
         let mut tui = Tui {
             client,
             backend: B::new(tx)?,
             evq,
 
+            prompt: None,
+            message: None,
+            popup: None,
+            focus: None,
+
+            theme,
+            yank: YankTarget::Clipboard,
+            yanked: None,
             size: Point::new(0, 0),
         };
         tui.update_size();
@@ -133,6 +424,15 @@ where
         Ok(tui)
     }
 
+    pub fn set_yank_target(&mut self, target: YankTarget) {
+        self.yank = target;
+    }
+
+    /// Restores the terminal and returns the text copied for `YankTarget::Stdout`
+    pub fn into_yanked(self) -> Option<String> {
+        self.yanked
+    }
+
     pub fn event_loop(&mut self) {
         loop {
             match self.evq.recv() {
@@ -151,19 +451,67 @@ where
     fn handle_event(&mut self, ev: &Event) -> bool {
         use self::Event::*;
         use termion::event::Event::*;
-        use termion::event::Key::*;
 
         match ev {
             TermResized => {
                 self.update_size();
                 self.redraw(true);
             }
-            Input(Key(Char(c))) => match c {
-                'q' => return false,
-                c => {
-                    self.client.handle_char(*c);
+            Terminate => return false,
+            Suspend => self.suspend(),
+            Continued => {
+                self.backend.resume();
+                self.update_size();
+                self.redraw(true);
+            }
+            Input(Key(key)) if self.popup.is_some() => {
+                if !self.popup.as_mut().unwrap().handle_key(*key) {
+                    self.popup = None;
+                }
+                self.redraw(true);
+            }
+            Input(Mouse(ev)) => {
+                if let Some(ev) = MouseEvent::from_termion(*ev) {
+                    self.handle_mouse(ev);
+                }
+            }
+            Input(Key(key)) if self.prompt.is_some() => self.handle_prompt_key(*key),
+            Input(Key(key)) => match (binding::lookup(BINDINGS, *key), key) {
+                (Some(Action::Quit), _) => return false,
+                (Some(Action::Help), _) => {
+                    self.message = None;
+                    self.handle_response(Response::Popup(self.help()));
+                    self.redraw(true);
+                }
+                (Some(Action::Suspend), _) => self.suspend(),
+                (Some(Action::FocusNext), _) => {
+                    let panes = self.client.panes().len();
+                    self.focus = match self.focus {
+                        None if panes > 0 => Some(0),
+                        Some(idx) if idx + 1 < panes => Some(idx + 1),
+                        _ => None,
+                    };
                     self.redraw(false);
                 }
+                (None, key) => {
+                    let had_message = self.message.take().is_some();
+                    let consumed = match self.focus {
+                        Some(idx) => self
+                            .client
+                            .panes()
+                            .into_iter()
+                            .nth(idx)
+                            .is_some_and(|pane| pane.handle_key(*key)),
+                        None => false,
+                    };
+                    let response = if consumed {
+                        Response::None
+                    } else {
+                        self.client.handle_key(*key)
+                    };
+                    let force = self.handle_response(response) || had_message;
+                    self.redraw(force);
+                }
             },
             _ => (),
         }
@@ -171,12 +519,148 @@ where
         true
     }
 
+    fn handle_mouse(&mut self, ev: MouseEvent) {
+        if let Some(popup) = &mut self.popup {
+            popup.handle_mouse(ev);
+            self.redraw(true);
+        } else if self.prompt.is_none() {
+            let had_message = self.message.take().is_some();
+            let mut panes = self.client.panes();
+            let hit = panes
+                .iter()
+                .position(|pane| pane.rect().is_some_and(|r| r.contains(ev.pos())));
+            // Clicks move the focus to what was clicked
+            if let MouseEvent::Click(_) = ev {
+                self.focus = hit;
+            }
+            let response = match hit {
+                Some(idx) => {
+                    panes[idx].handle_mouse(ev);
+                    Response::None
+                }
+                None => {
+                    drop(panes);
+                    self.client.handle_mouse(ev)
+                }
+            };
+            let force = self.handle_response(response) || had_message;
+            self.redraw(force);
+        }
+    }
+
+    fn handle_prompt_key(&mut self, key: termion::event::Key) {
+        use termion::event::Key::*;
+
+        let prompt = self.prompt.as_mut().unwrap();
+        let ev = match key {
+            Char('\n') => PromptEvent::Submitted,
+            Esc => PromptEvent::Cancelled,
+            Backspace if prompt.input.is_empty() => PromptEvent::Cancelled,
+            Backspace => {
+                prompt.input.pop();
+                PromptEvent::Changed
+            }
+            Char(c) if !c.is_control() => {
+                prompt.input.push(c);
+                PromptEvent::Changed
+            }
+            _ => return,
+        };
+
+        let input = if ev == PromptEvent::Changed {
+            prompt.input.clone()
+        } else {
+            self.prompt.take().unwrap().input
+        };
+        let response = self.client.handle_prompt(&input, ev);
+        self.handle_response(response);
+        self.redraw(true);
+    }
+
+    fn suspend(&mut self) {
+        self.backend.suspend();
+        // The terminal may have been resized meanwhile
+        self.update_size();
+        self.redraw(true);
+    }
+
+    fn help(&self) -> Popup {
+        let mut sections = self.client.help();
+        sections.push(("General", binding::help(BINDINGS)));
+        sections.push((
+            "Popups",
+            binding::help(SCROLL_BINDINGS)
+                .into_iter()
+                .chain(binding::help(POPUP_CLOSE_BINDINGS))
+                .collect(),
+        ));
+        Popup::new("Key bindings", binding::help_lines(&sections))
+    }
+
+    /// Returns whether the prompt line changed
+    fn handle_response(&mut self, response: Response) -> bool {
+        match response {
+            Response::None => false,
+            Response::Prompt(prefix) => {
+                self.prompt = Some(Prompt {
+                    prefix,
+                    input: String::new(),
+                });
+                true
+            }
+            Response::Message(message) => {
+                self.message = Some(message);
+                true
+            }
+            Response::Popup(popup) => {
+                self.popup = Some(popup);
+                true
+            }
+            Response::Yank(text) => {
+                let message = match &self.yank {
+                    YankTarget::Clipboard => {
+                        self.backend.copy(&text);
+                        "Copied to clipboard".to_string()
+                    }
+                    YankTarget::Stdout => {
+                        self.yanked = Some(text);
+                        "Printed when quitting".to_string()
+                    }
+                    YankTarget::File(path) => match fs::write(path, text) {
+                        Ok(()) => format!("Written to {}", path.display()),
+                        Err(e) => format!("Cannot write {}: {}", path.display(), e),
+                    },
+                };
+                self.message = Some(message);
+                true
+            }
+        }
+    }
+
+    /// Tells the panes which one is focused, the client gets the focus of a closed pane
+    fn update_focus(&mut self) {
+        let mut panes = self.client.panes();
+        let focus = self.focus.filter(|&idx| idx < panes.len());
+        for (idx, pane) in panes.iter_mut().enumerate() {
+            pane.set_focused(focus == Some(idx));
+        }
+        self.focus = focus;
+    }
+
     fn redraw(&mut self, force: bool) {
+        self.update_focus();
         let frame = self.backend.get_frame_mut();
+        frame.set_theme(self.theme);
 
         let draw = if self.client.dirty() || force {
-            let rect = Rect::new(Point::new(0, 0), self.size - Point::new(1, 1));
-            self.client.draw(&rect, frame);
+            let screen = Rect::new(Point::new(0, 0), self.size - Point::new(1, 1));
+            let (rect, _) = Split::new(Direction::Vertical, Size::Last(1)).split(&screen);
+            let client = &mut self.client;
+            frame.clipped(&rect, |frame| client.draw(&rect, frame));
+            if let Some(popup) = &mut self.popup {
+                frame.clipped(&rect, |frame| popup.draw(&rect, frame));
+            }
+            self.draw_prompt_line();
             true
         } else {
             false
@@ -187,7 +671,63 @@ where
         }
     }
 
+    fn draw_prompt_line(&mut self) {
+        let y = self.size.y - 1;
+        if y < 0 {
+            return;
+        }
+
+        let frame = self.backend.get_frame_mut();
+        frame.clear_rect(&Rect::new(Point::new(0, y), Point::new(self.size.x - 1, y)));
+
+        let (text, cursor) = match (&self.prompt, &self.message) {
+            (Some(prompt), _) => (format!("{}{}", prompt.prefix, prompt.input), true),
+            (None, Some(message)) => (message.clone(), false),
+            (None, None) => return,
+        };
+
+        let x = frame.print(Point::new(0, y), &text, Style::new());
+        if cursor {
+            let style = frame.theme().cursor;
+            frame.set_style(Point::new(x, y), style);
+        }
+    }
+
     fn update_size(&mut self) {
         self.size = self.backend.update_size();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(frame: &Frame, y: i32) -> Vec<&str> {
+        frame
+            .line(y)
+            .iter()
+            .map(|c| c.symbol.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn wide_glyphs() {
+        let mut frame = Frame::new(Point::new(6, 1));
+        assert_eq!(frame.print(Point::new(0, 0), "日e\u{301}本", Style::new()), 5);
+        assert_eq!(row(&frame, 0), vec!["日", "", "e\u{301}", "本", "", " "]);
+
+        // Overwriting either half of a wide glyph blanks the other one
+        frame.put(Point::new(1, 0), 'a', Style::new());
+        frame.put(Point::new(3, 0), 'b', Style::new());
+        assert_eq!(row(&frame, 0), vec![" ", "a", "e\u{301}", "b", " ", " "]);
+
+        // Marks without a base attach to the glyph before them
+        frame.print(Point::new(0, 0), "本", Style::new());
+        frame.print(Point::new(2, 0), "\u{301}", Style::new());
+        assert_eq!(row(&frame, 0), vec!["本\u{301}", "", "e\u{301}", "b", " ", " "]);
+
+        // Wide glyphs not fitting before the edge and control characters are replaced
+        frame.print(Point::new(3, 0), "\u{1b}c日", Style::new());
+        assert_eq!(row(&frame, 0), vec!["本\u{301}", "", "e\u{301}", "\u{fffd}", "c", " "]);
+    }
+}
@@ -0,0 +1,58 @@
+/// Case insensitive unless the pattern contains upper case characters, like vim's `smartcase`
+fn ignore_case(pattern: &str) -> bool {
+    !pattern.chars().any(char::is_uppercase)
+}
+
+fn fold(c: char, ignore_case: bool) -> char {
+    if ignore_case {
+        c.to_lowercase().next().unwrap_or(c)
+    } else {
+        c
+    }
+}
+
+/// Returns start and length in chars of all non-overlapping occurrences of `pattern` in `text`
+pub fn find_matches(text: &str, pattern: &str) -> Vec<(usize, usize)> {
+    let ignore_case = ignore_case(pattern);
+    let text: Vec<char> = text.chars().map(|c| fold(c, ignore_case)).collect();
+    let pattern: Vec<char> = pattern.chars().map(|c| fold(c, ignore_case)).collect();
+
+    let mut matches = Vec::new();
+    if pattern.is_empty() {
+        return matches;
+    }
+
+    let mut start = 0;
+    while start + pattern.len() <= text.len() {
+        if text[start..start + pattern.len()] == pattern[..] {
+            matches.push((start, pattern.len()));
+            start += pattern.len();
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+pub fn is_match(text: &str, pattern: &str) -> bool {
+    !find_matches(text, pattern).is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        assert_eq!(
+            find_matches("gcc -c a.c", "c"),
+            vec![(1, 1), (2, 1), (5, 1), (9, 1)]
+        );
+        assert_eq!(find_matches("aaaa", "aa"), vec![(0, 2), (2, 2)]);
+        assert_eq!(find_matches("Make make", "make"), vec![(0, 4), (5, 4)]);
+        assert_eq!(find_matches("Make make", "Make"), vec![(0, 4)]);
+        assert_eq!(find_matches("└── ls", "ls"), vec![(4, 2)]);
+        assert_eq!(find_matches("ls", ""), vec![]);
+        assert_eq!(find_matches("ls", "lsblk"), vec![]);
+    }
+}
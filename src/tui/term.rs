@@ -1,65 +1,148 @@
 use termion::input::TermReadEventsAndRaw;
-use termion::raw::IntoRawMode;
-use termion::{color, cursor, raw};
+use termion::{cursor, screen, style};
 
-use nix::sys::signal;
+use nix::sys::{signal, termios};
 
 use crate::tui;
+use crate::tui::theme::{Attrs, Bg, Color, Fg, SetAttrs};
+use crate::util::{self, Point};
 use crate::Result;
 
 use std::io;
 use std::io::Write;
+use std::panic;
 use std::sync::atomic;
 use std::sync::mpsc;
+use std::sync::{Mutex, Once};
 use std::thread;
 use std::time;
 
-static mut RECVD_SIGWINCH: atomic::AtomicBool = atomic::AtomicBool::new(false);
+static RECVD_SIGWINCH: atomic::AtomicBool = atomic::AtomicBool::new(false);
+static RECVD_SIGTERM: atomic::AtomicBool = atomic::AtomicBool::new(false);
+static RECVD_SIGTSTP: atomic::AtomicBool = atomic::AtomicBool::new(false);
+static RECVD_SIGCONT: atomic::AtomicBool = atomic::AtomicBool::new(false);
 
-extern "C" fn handle_sigwinch(_: libc::c_int) {
+extern "C" fn handle_signal(sig: libc::c_int) {
+    let recvd = match sig {
+        libc::SIGWINCH => &RECVD_SIGWINCH,
+        libc::SIGTSTP => &RECVD_SIGTSTP,
+        libc::SIGCONT => &RECVD_SIGCONT,
+        _ => &RECVD_SIGTERM,
+    };
+    recvd.store(true, atomic::Ordering::Relaxed);
+}
+
+/// Signals turned into `tui::Event`s while the TUI runs
+const SIGNALS: [signal::Signal; 5] = [
+    signal::Signal::SIGWINCH,
+    signal::Signal::SIGINT,
+    signal::Signal::SIGTERM,
+    signal::Signal::SIGTSTP,
+    signal::Signal::SIGCONT,
+];
+
+const ENTER_MOUSE: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h";
+const EXIT_MOUSE: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+
+/// Whether the terminal is set up for the TUI
+static ACTIVE: atomic::AtomicBool = atomic::AtomicBool::new(false);
+/// Terminal modes from before the TUI took over, restored when leaving it
+static SAVED_MODES: Mutex<Option<termios::Termios>> = Mutex::new(None);
+static PANIC_HOOK: Once = Once::new();
+
+/// Switches to raw mode, the alternate screen and mouse reporting, again after a stop
+fn enter() -> Result<()> {
+    let mut modes = termios::tcgetattr(libc::STDIN_FILENO)?;
+    if !ACTIVE.swap(true, atomic::Ordering::SeqCst) {
+        *SAVED_MODES.lock().unwrap() = Some(modes.clone());
+    }
+    termios::cfmakeraw(&mut modes);
+    termios::tcsetattr(libc::STDIN_FILENO, termios::SetArg::TCSANOW, &modes)?;
+
+    let mut stdout = io::stdout();
+    write!(stdout, "{}{}{}", screen::ToAlternateScreen, ENTER_MOUSE, cursor::Hide)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Gives the terminal back in the state the TUI found it in
+fn leave() {
+    if !ACTIVE.swap(false, atomic::Ordering::SeqCst) {
+        return;
+    }
+    let mut stdout = io::stdout();
+    // Errors are ignored, this also runs while panicking
+    let _ = write!(
+        stdout,
+        "{}{}{}{}",
+        style::Reset,
+        cursor::Show,
+        EXIT_MOUSE,
+        screen::ToMainScreen
+    );
+    let _ = stdout.flush();
+    // Not waiting for the lock, a panic may have happened while holding it
+    if let Ok(Some(modes)) = SAVED_MODES.try_lock().as_deref() {
+        let _ = termios::tcsetattr(libc::STDIN_FILENO, termios::SetArg::TCSANOW, modes);
+    }
+}
+
+fn set_handler(sig: signal::Signal, handler: signal::SigHandler) {
+    let action = signal::SigAction::new(
+        handler,
+        signal::SaFlags::SA_RESTART,
+        signal::SigSet::all(),
+    );
     unsafe {
-        RECVD_SIGWINCH.store(true, atomic::Ordering::Relaxed);
+        signal::sigaction(sig, &action).unwrap();
     }
 }
 
 pub struct Term {
     size: (u16, u16),
-    stdout: raw::RawTerminal<io::Stdout>,
+    stdout: io::Stdout,
 
     frame_idx: usize,
     frame_buf: [tui::Frame; 2],
+    /// Whether scrolled lines are moved by the terminal instead of being redrawn
+    scroll_regions: bool,
 }
 
 impl Drop for Term {
     fn drop(&mut self) {
-        write!(self.stdout, "{}", cursor::Show).unwrap();
+        leave();
+        for &sig in &SIGNALS {
+            set_handler(sig, signal::SigHandler::SigDfl);
+        }
     }
 }
 
 impl tui::Backend for Term {
     fn new(channel: mpsc::SyncSender<tui::Event>) -> Result<Self> {
-        let mut term = Term {
+        enter()?;
+        // The panic message would be lost on the alternate screen
+        PANIC_HOOK.call_once(|| {
+            let hook = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                leave();
+                hook(info);
+            }));
+        });
+        let term = Term {
             size: (0, 0),
-            stdout: io::stdout().into_raw_mode()?,
+            stdout: io::stdout(),
 
             frame_idx: 0,
             frame_buf: [
                 tui::Frame::new(tui::Point::new(0, 0)),
                 tui::Frame::new(tui::Point::new(0, 0)),
             ],
+            scroll_regions: true,
         };
-        write!(term.stdout, "{}", cursor::Hide).unwrap();
 
-        // Install sig handler
-        {
-            let sighandler = signal::SigAction::new(
-                signal::SigHandler::Handler(handle_sigwinch),
-                signal::SaFlags::SA_RESTART,
-                signal::SigSet::all(),
-            );
-            unsafe {
-                signal::sigaction(signal::Signal::SIGWINCH, &sighandler).unwrap();
-            }
+        // Install sig handlers, they only set flags the listener turns into events
+        for &sig in &SIGNALS {
+            set_handler(sig, signal::SigHandler::Handler(handle_signal));
         }
 
         // Spawn termion event listener
@@ -93,10 +176,17 @@ impl tui::Backend for Term {
                 Err(_) => return,
             }
 
-            if unsafe { RECVD_SIGWINCH.compare_and_swap(true, false, atomic::Ordering::Relaxed) }
-                && channel.send(tui::Event::TermResized).is_err()
-            {
-                return;
+            for (recvd, ev) in &[
+                (&RECVD_SIGWINCH, tui::Event::TermResized),
+                (&RECVD_SIGTERM, tui::Event::Terminate),
+                (&RECVD_SIGTSTP, tui::Event::Suspend),
+                (&RECVD_SIGCONT, tui::Event::Continued),
+            ] {
+                if recvd.swap(false, atomic::Ordering::Relaxed)
+                    && channel.send(ev.clone()).is_err()
+                {
+                    return;
+                }
             }
 
             thread::sleep(time::Duration::from_millis(10));
@@ -109,19 +199,10 @@ impl tui::Backend for Term {
         let frame = &self.frame_buf[self.frame_idx];
         let old_frame = &self.frame_buf[(self.frame_idx + 1) % 2];
 
-        for (idx, cell) in frame.cells().enumerate() {
-            if *cell != old_frame.cells[idx] || force {
-                write!(
-                    self.stdout,
-                    "{}",
-                    cursor::Goto(cell.pos.x as u16 + 1, cell.pos.y as u16 + 1)
-                )
-                .unwrap();
-                write!(self.stdout, "{}", color::Fg(color::AnsiValue(cell.fg))).unwrap();
-                write!(self.stdout, "{}", color::Bg(color::AnsiValue(cell.bg))).unwrap();
-                write!(self.stdout, "{}", cell.c).unwrap();
-            }
-        }
+        // Written at once, instead of one write per escape sequence
+        let mut buf = Vec::new();
+        render(&mut buf, frame, old_frame, force, self.scroll_regions).unwrap();
+        self.stdout.write_all(&buf).unwrap();
         self.stdout.flush().unwrap();
 
         self.frame_idx = (self.frame_idx + 1) % 2;
@@ -131,7 +212,7 @@ impl tui::Backend for Term {
         self.size = match termion::terminal_size() {
             Ok(sz) => sz,
             Err(e) => {
-                panic!(format!("{}", e));
+                panic!("{}", e);
             }
         };
 
@@ -143,7 +224,423 @@ impl tui::Backend for Term {
         size
     }
 
+    fn suspend(&mut self) {
+        leave();
+        // Stop the way the default action does, then take the terminal again. Being
+        // continued is handled right here, not by a `Continued` event.
+        let sigs = [signal::Signal::SIGTSTP, signal::Signal::SIGCONT];
+        for &sig in &sigs {
+            set_handler(sig, signal::SigHandler::SigDfl);
+        }
+        signal::raise(signal::Signal::SIGTSTP).unwrap();
+        for &sig in &sigs {
+            set_handler(sig, signal::SigHandler::Handler(handle_signal));
+        }
+        self.resume();
+    }
+
+    fn resume(&mut self) {
+        // The shell resets the modes of stopped jobs
+        if let Err(e) = enter() {
+            panic!("Cannot set up terminal: {}", e);
+        }
+    }
+
+    fn copy(&mut self, text: &str) {
+        write!(self.stdout, "\x1b]52;c;{}\x07", util::base64(text.as_bytes())).unwrap();
+        self.stdout.flush().unwrap();
+    }
+
     fn get_frame_mut(&mut self) -> &mut tui::Frame {
         &mut self.frame_buf[self.frame_idx]
     }
 }
+
+/// Unchanged cells rewritten to join two runs of changed cells, instead of moving the cursor
+const MAX_GAP: i32 = 3;
+
+/// What the terminal currently draws with and where it writes next
+struct Pen {
+    fg: Color,
+    bg: Color,
+    attrs: Attrs,
+    /// Unknown after scrolling or writing into the last column
+    cursor: Option<Point>,
+}
+
+impl Pen {
+    fn set<W: Write>(&mut self, w: &mut W, cell: &tui::Cell) -> io::Result<()> {
+        if cell.fg != self.fg {
+            write!(w, "{}", Fg(cell.fg))?;
+            self.fg = cell.fg;
+        }
+        if cell.bg != self.bg {
+            write!(w, "{}", Bg(cell.bg))?;
+            self.bg = cell.bg;
+        }
+        if cell.attrs != self.attrs {
+            write!(
+                w,
+                "{}",
+                SetAttrs {
+                    from: self.attrs,
+                    to: cell.attrs
+                }
+            )?;
+            self.attrs = cell.attrs;
+        }
+        Ok(())
+    }
+
+    fn matches(&self, cell: &tui::Cell) -> bool {
+        (cell.fg, cell.bg, cell.attrs) == (self.fg, self.bg, self.attrs)
+    }
+}
+
+/// Lines `top..=bottom` whose content moved up by `shift` lines, or down if negative
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ScrollRegion {
+    top: i32,
+    bottom: i32,
+    shift: i32,
+}
+
+impl ScrollRegion {
+    /// Line of the old frame now shown in line `y`, if the terminal still has it
+    fn source(self, y: i32) -> Option<i32> {
+        if y < self.top || y > self.bottom {
+            Some(y)
+        } else if y + self.shift >= self.top && y + self.shift <= self.bottom {
+            Some(y + self.shift)
+        } else {
+            None
+        }
+    }
+}
+
+fn same_cell(a: &tui::Cell, b: &tui::Cell) -> bool {
+    (&a.symbol, a.fg, a.bg, a.attrs) == (&b.symbol, b.fg, b.bg, b.attrs)
+}
+
+fn same_line(frame: &tui::Frame, y: i32, old: &tui::Frame, old_y: i32) -> bool {
+    let old_line = old.line(old_y);
+    frame.line(y).iter().zip(old_line).all(|(a, b)| same_cell(a, b))
+}
+
+/// Finds the shift of lines which makes most of the changed lines match the old frame
+///
+/// Scrolling by more than half of the changed lines redraws more than it saves.
+fn find_scroll(frame: &tui::Frame, old: &tui::Frame) -> Option<ScrollRegion> {
+    let changed: Vec<i32> = (0..frame.size.y)
+        .filter(|&y| !same_line(frame, y, old, y))
+        .collect();
+    let (top, bottom) = (*changed.first()?, *changed.last()?);
+    let height = bottom - top + 1;
+
+    (1..=height / 2)
+        .flat_map(|shift| vec![shift, -shift])
+        .map(|shift| {
+            let region = ScrollRegion { top, bottom, shift };
+            let matching = (top..=bottom)
+                .filter(|&y| {
+                    region
+                        .source(y)
+                        .is_some_and(|old_y| same_line(frame, y, old, old_y))
+                })
+                .count();
+            (matching, region)
+        })
+        .filter(|&(matching, _)| matching * 2 > height as usize)
+        .max_by_key(|&(matching, region)| (matching, -region.shift.abs()))
+        .map(|(_, region)| region)
+}
+
+/// Writes the escape sequences turning the terminal showing `old` into `frame`
+///
+/// Only changed cells are written, runs of them without moving the cursor in between,
+/// and colours and attributes only when they differ from the previous cell's.
+fn render<W: Write>(
+    w: &mut W,
+    frame: &tui::Frame,
+    old: &tui::Frame,
+    force: bool,
+    scroll_regions: bool,
+) -> io::Result<()> {
+    // Start from known attributes
+    write!(w, "{}", style::Reset)?;
+    let mut pen = Pen {
+        fg: Color::Default,
+        bg: Color::Default,
+        attrs: Attrs::NONE,
+        cursor: None,
+    };
+
+    let region = if scroll_regions && !force {
+        find_scroll(frame, old)
+    } else {
+        None
+    };
+    if let Some(region) = region {
+        write!(w, "\x1b[{};{}r", region.top + 1, region.bottom + 1)?;
+        // Index at the bottom margin and reverse index at the top one scroll the region,
+        // unlike the scroll commands every VT100 compatible terminal has them
+        if region.shift > 0 {
+            write!(w, "{}", cursor::Goto(1, region.bottom as u16 + 1))?;
+            write!(w, "{}", "\x1bD".repeat(region.shift as usize))?;
+        } else {
+            write!(w, "{}", cursor::Goto(1, region.top as u16 + 1))?;
+            write!(w, "{}", "\x1bM".repeat(-region.shift as usize))?;
+        }
+        // Also moves the cursor home
+        write!(w, "\x1b[r")?;
+    }
+
+    let width = frame.size.x;
+    for y in 0..frame.size.y {
+        let line = frame.line(y);
+        let old_line = region.map_or(Some(y), |region| region.source(y)).map(|y| old.line(y));
+        let changed = |x: usize| {
+            force || old_line.is_none_or(|old_line| !same_cell(&line[x], &old_line[x]))
+        };
+
+        for x in 0..width {
+            let cell = &line[x as usize];
+            // Cells covered by a wide glyph are drawn together with it
+            if cell.symbol.is_empty() || !changed(x as usize) {
+                continue;
+            }
+
+            match pen.cursor {
+                Some(cursor) if cursor == cell.pos => (),
+                // Rewriting a few unchanged cells is shorter than moving over them
+                Some(cursor)
+                    if cursor.y == y
+                        && cursor.x < x
+                        && x - cursor.x <= MAX_GAP
+                        && line[cursor.x as usize..x as usize]
+                            .iter()
+                            .all(|c| c.symbol.len() == 1 && pen.matches(c)) =>
+                {
+                    for c in &line[cursor.x as usize..x as usize] {
+                        write!(w, "{}", c.symbol)?;
+                    }
+                }
+                _ => write!(w, "{}", cursor::Goto(x as u16 + 1, y as u16 + 1))?,
+            }
+
+            pen.set(w, cell)?;
+            write!(w, "{}", cell.symbol)?;
+            let next = x + if line.get(x as usize + 1).is_some_and(|c| c.symbol.is_empty()) {
+                2
+            } else {
+                1
+            };
+            // Writing into the last column leaves the cursor there until the next character
+            pen.cursor = if next < width {
+                Some(Point::new(next, y))
+            } else {
+                None
+            };
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tui::theme::Style;
+    use crate::tui::{text, Frame};
+
+    use std::cmp;
+
+    /// Terminal understanding the sequences `render` writes, keeping only the characters
+    struct Screen {
+        lines: Vec<Vec<String>>,
+        cursor: Point,
+        region: (usize, usize),
+    }
+
+    impl Screen {
+        fn new(size: Point) -> Self {
+            Screen {
+                lines: vec![vec![" ".to_string(); size.x as usize]; size.y as usize],
+                cursor: Point::new(0, 0),
+                region: (0, size.y as usize - 1),
+            }
+        }
+
+        fn apply(&mut self, output: &[u8]) {
+            let output = String::from_utf8(output.to_vec()).unwrap();
+            for (idx, part) in output.split('\x1b').enumerate() {
+                let text = if idx == 0 {
+                    part
+                } else if part.starts_with('[') {
+                    let end = part.find(|c| ('@'..='~').contains(&c) && c != '[').unwrap();
+                    self.csi(&part[1..end], part[end..].chars().next().unwrap());
+                    &part[end + 1..]
+                } else {
+                    self.esc(part.chars().next().unwrap());
+                    &part[1..]
+                };
+                for (g, width) in text::graphemes(text) {
+                    let line = &mut self.lines[self.cursor.y as usize];
+                    let x = self.cursor.x as usize;
+                    line[x] = g.to_string();
+                    if width == 2 {
+                        line[x + 1] = String::new();
+                    }
+                    self.cursor.x = cmp::min(x + width, line.len() - 1) as i32;
+                }
+            }
+        }
+
+        fn csi(&mut self, params: &str, cmd: char) {
+            let params: Vec<usize> = params.split(';').filter_map(|p| p.parse().ok()).collect();
+            match cmd {
+                'H' => self.cursor = Point::new(params[1] as i32 - 1, params[0] as i32 - 1),
+                'r' => {
+                    self.region = match params[..] {
+                        [top, bottom] => (top - 1, bottom - 1),
+                        _ => (0, self.lines.len() - 1),
+                    };
+                    self.cursor = Point::new(0, 0);
+                }
+                'm' => (),
+                _ => panic!("Unexpected sequence {}", cmd),
+            }
+        }
+
+        fn esc(&mut self, cmd: char) {
+            let (top, bottom) = self.region;
+            let blank = vec![" ".to_string(); self.lines[0].len()];
+            match cmd {
+                'D' if self.cursor.y as usize == bottom => {
+                    self.lines.remove(top);
+                    self.lines.insert(bottom, blank);
+                }
+                'M' if self.cursor.y as usize == top => {
+                    self.lines.remove(bottom);
+                    self.lines.insert(top, blank);
+                }
+                _ => panic!("Unexpected sequence {}", cmd),
+            }
+        }
+
+        fn shows(&self, frame: &Frame) -> bool {
+            (0..frame.size.y).all(|y| {
+                let symbols: Vec<&String> = frame.line(y).iter().map(|c| &c.symbol).collect();
+                self.lines[y as usize].iter().eq(symbols)
+            })
+        }
+    }
+
+    /// Tree listing like the TreeView draws it, starting at node `first`
+    fn listing(first: usize, selected: usize) -> Frame {
+        let mut frame = Frame::new(Point::new(80, 24));
+        for y in 0..23 {
+            let n = first + y;
+            let p = Point::new(0, y as i32);
+            let x = frame.print(p, "    ├── [+] ", Style::new().fg(Color::Indexed(244)));
+            let x = x + frame.print(
+                p + Point::new(x, 0),
+                "make",
+                Style::new().fg(Color::Indexed(255)).attrs(Attrs::BOLD),
+            );
+            let args = [" -C src all", " -j4 -C 目录/lib{} install", " -f build/Makefile.{}"];
+            frame.print(
+                p + Point::new(x, 0),
+                &format!("{}  [{}ms]", args[n % 3].replace("{}", &n.to_string()), n * 7 % 100),
+                Style::new(),
+            );
+            if n == selected {
+                for x in 0..80 {
+                    frame.set_style(Point::new(x, y as i32), frame.theme().selection);
+                }
+            }
+        }
+        let status = Style::new().bg(Color::Indexed(238));
+        frame.print(Point::new(0, 23), &format!("{:<80}", format!("line {}", selected)), status);
+        frame
+    }
+
+    fn bytes(frame: &Frame, old: &Frame, force: bool, scroll_regions: bool) -> Vec<u8> {
+        let mut buf = Vec::new();
+        render(&mut buf, frame, old, force, scroll_regions).unwrap();
+        buf
+    }
+
+    #[test]
+    fn render_frames() {
+        let blank = Frame::new(Point::new(80, 24));
+        let frames = [
+            listing(0, 0),
+            listing(0, 1),
+            listing(1, 2),
+            listing(4, 6),
+            listing(2, 4),
+            listing(30, 30),
+        ];
+
+        for &scroll_regions in &[false, true] {
+            let mut screen = Screen::new(Point::new(80, 24));
+            screen.apply(&bytes(&frames[0], &blank, true, scroll_regions));
+            assert!(screen.shows(&frames[0]));
+            for pair in frames.windows(2) {
+                screen.apply(&bytes(&pair[1], &pair[0], false, scroll_regions));
+                assert!(screen.shows(&pair[1]));
+            }
+        }
+
+        assert!(bytes(&frames[0], &frames[0], false, true).len() <= 4);
+        let scroll = String::from_utf8(bytes(&frames[2], &frames[1], false, true)).unwrap();
+        assert!(scroll.starts_with("\x1b[m\x1b[1;24r\x1b[24;1H\x1bD\x1b[r"));
+        let scroll = String::from_utf8(bytes(&frames[4], &frames[3], false, true)).unwrap();
+        assert!(scroll.starts_with("\x1b[m\x1b[1;24r\x1b[1;1H\x1bM\x1bM\x1b[r"));
+    }
+
+    /// Common screen updates as (name, frame, previous frame, forced redraw)
+    fn frame_updates() -> Vec<(&'static str, Frame, Frame, bool)> {
+        let blank = Frame::new(Point::new(80, 24));
+        vec![
+            ("full redraw", listing(0, 0), blank, true),
+            ("cursor move", listing(0, 1), listing(0, 0), false),
+            ("line scroll", listing(1, 23), listing(0, 22), false),
+            ("page scroll", listing(23, 23), listing(0, 22), false),
+        ]
+    }
+
+    /// Prints the bytes written per frame update, run with
+    /// `cargo test bytes_per_frame -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bytes_per_frame() {
+        println!();
+        println!("{:<12} {:>10} {:>14}", "update", "bytes", "scroll regions");
+        for (name, frame, old, force) in frame_updates() {
+            println!(
+                "{:<12} {:>10} {:>14}",
+                name,
+                bytes(&frame, &old, force, false).len(),
+                bytes(&frame, &old, force, true).len()
+            );
+        }
+    }
+
+    /// Bounds a little above what `bytes_per_frame` measured, catching render regressions
+    #[test]
+    fn frame_update_size() {
+        // Measured 3449, 318, 1400 (418 with scroll regions) and 1356 bytes
+        let bounds = [(3800, 3800), (350, 350), (1550, 460), (1500, 1500)];
+        for ((name, frame, old, force), bounds) in frame_updates().iter().zip(&bounds) {
+            let lens = (
+                bytes(frame, old, *force, false).len(),
+                bytes(frame, old, *force, true).len(),
+            );
+            let fits = lens.0 <= bounds.0 && lens.1 <= bounds.1;
+            assert!(fits, "{}: {:?}", name, lens);
+        }
+    }
+}
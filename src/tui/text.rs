@@ -0,0 +1,59 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use std::cmp;
+
+/// Shown for control characters which were not escaped, they are never written to the terminal
+const REPLACEMENT: &str = "\u{fffd}";
+
+/// Splits `text` into grapheme clusters and the number of columns each one takes
+///
+/// Wide glyphs take two columns, combining marks are part of the cluster they follow. A
+/// cluster without a base character, like a leading combining mark, takes no column.
+pub fn graphemes(text: &str) -> impl Iterator<Item = (&str, usize)> {
+    text.graphemes(true).map(|g| {
+        if g.chars().any(char::is_control) {
+            (REPLACEMENT, 1)
+        } else if g.contains('\u{fe0f}') {
+            // Emoji presentation selector
+            (g, 2)
+        } else {
+            (g, cmp::min(g.width(), 2))
+        }
+    })
+}
+
+/// Number of terminal columns `text` takes
+pub fn width(text: &str) -> usize {
+    graphemes(text).map(|(_, w)| w).sum()
+}
+
+/// Columns taken by the first `chars` characters of `text`
+pub fn char_column(text: &str, chars: usize) -> usize {
+    let end = text
+        .char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(idx, _)| idx);
+    width(&text[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths() {
+        assert_eq!(width("gcc -c a.c"), 10);
+        assert_eq!(width("日本語.c"), 8);
+        assert_eq!(width("cafe\u{301}"), 4);
+        assert_eq!(width("👍"), 2);
+        assert_eq!(width("❤\u{fe0f}"), 2);
+        assert_eq!(width("a\tb"), 3);
+        assert_eq!(char_column("日本語.c", 2), 4);
+        assert_eq!(char_column("ab", 5), 2);
+
+        let g: Vec<_> = graphemes("e\u{301}日\u{1b}").collect();
+        assert_eq!(g, vec![("e\u{301}", 1), ("日", 2), (REPLACEMENT, 1)]);
+        assert_eq!(graphemes("\u{301}").next(), Some(("\u{301}", 0)));
+    }
+}
@@ -0,0 +1,484 @@
+use serde_derive::Deserialize;
+
+use crate::tui::tv::SpanKind;
+use crate::Result;
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path;
+
+/// Colour of a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// Whatever the terminal uses when no colour is set
+    Default,
+    /// One of the 16 colours every colour terminal supports, 8 to 15 are the bright ones
+    Basic(u8),
+    /// Entry of the 256 colour palette
+    Indexed(u8),
+}
+
+const BASIC_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+impl Color {
+    /// Parses `default`, a basic colour name like `red` or `bright-red`, or a palette index
+    pub fn from_name(name: &str) -> Option<Color> {
+        if name == "default" {
+            return Some(Color::Default);
+        }
+        if let Ok(n) = name.parse() {
+            return Some(Color::Indexed(n));
+        }
+        let (bright, name) = match name.strip_prefix("bright-") {
+            Some(name) => (8, name),
+            None => (0, name),
+        };
+        BASIC_NAMES
+            .iter()
+            .position(|&n| n == name)
+            .map(|n| Color::Basic(n as u8 + bright))
+    }
+}
+
+/// Escape sequence setting the foreground colour
+pub struct Fg(pub Color);
+
+/// Escape sequence setting the background colour
+pub struct Bg(pub Color);
+
+impl fmt::Display for Fg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Color::Default => write!(f, "\x1b[39m"),
+            Color::Basic(n) if n < 8 => write!(f, "\x1b[{}m", 30 + n),
+            Color::Basic(n) => write!(f, "\x1b[{}m", 90 + n - 8),
+            Color::Indexed(n) => write!(f, "\x1b[38;5;{}m", n),
+        }
+    }
+}
+
+impl fmt::Display for Bg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Color::Default => write!(f, "\x1b[49m"),
+            Color::Basic(n) if n < 8 => write!(f, "\x1b[{}m", 40 + n),
+            Color::Basic(n) => write!(f, "\x1b[{}m", 100 + n - 8),
+            Color::Indexed(n) => write!(f, "\x1b[48;5;{}m", n),
+        }
+    }
+}
+
+/// Set of text attributes like bold or underline
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Attrs(u8);
+
+/// Each attribute with its name and the SGR codes turning it on and off
+const ATTRS: [(Attrs, &str, u8, u8); 5] = [
+    (Attrs::BOLD, "bold", 1, 22),
+    (Attrs::DIM, "dim", 2, 22),
+    (Attrs::ITALIC, "italic", 3, 23),
+    (Attrs::UNDERLINE, "underline", 4, 24),
+    (Attrs::REVERSE, "reverse", 7, 27),
+];
+
+impl Attrs {
+    pub const NONE: Attrs = Attrs(0);
+    pub const BOLD: Attrs = Attrs(1);
+    pub const DIM: Attrs = Attrs(1 << 1);
+    pub const ITALIC: Attrs = Attrs(1 << 2);
+    pub const UNDERLINE: Attrs = Attrs(1 << 3);
+    pub const REVERSE: Attrs = Attrs(1 << 4);
+
+    pub fn from_name(name: &str) -> Option<Attrs> {
+        ATTRS.iter().find(|a| a.1 == name).map(|a| a.0)
+    }
+
+    pub const fn union(self, other: Attrs) -> Attrs {
+        Attrs(self.0 | other.0)
+    }
+
+    pub fn contains(self, other: Attrs) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    fn remove(self, other: Attrs) -> Attrs {
+        Attrs(self.0 & !other.0)
+    }
+}
+
+/// Escape sequence switching the terminal from the attributes `from` to `to`, leaving
+/// the colours alone
+pub struct SetAttrs {
+    pub from: Attrs,
+    pub to: Attrs,
+}
+
+impl fmt::Display for SetAttrs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut codes = Vec::new();
+        let mut current = self.from;
+        for &(attr, _, _, off) in &ATTRS {
+            if current.contains(attr) && !self.to.contains(attr) {
+                codes.push(off);
+                // Bold and dim are both turned off by the same code
+                for &(other, _, _, other_off) in &ATTRS {
+                    if other_off == off {
+                        current = current.remove(other);
+                    }
+                }
+            }
+        }
+        for &(attr, _, on, _) in &ATTRS {
+            if self.to.contains(attr) && !current.contains(attr) {
+                codes.push(on);
+            }
+        }
+
+        if codes.is_empty() {
+            return Ok(());
+        }
+        let codes: Vec<String> = codes.iter().map(u8::to_string).collect();
+        write!(f, "\x1b[{}m", codes.join(";"))
+    }
+}
+
+/// Colours and attributes drawn over a cell, unset colours keep what is below and
+/// attributes are added to the ones below
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub attrs: Attrs,
+}
+
+impl Style {
+    pub const fn new() -> Self {
+        Style {
+            fg: None,
+            bg: None,
+            attrs: Attrs::NONE,
+        }
+    }
+
+    pub const fn fg(mut self, fg: Color) -> Self {
+        self.fg = Some(fg);
+        self
+    }
+
+    pub const fn bg(mut self, bg: Color) -> Self {
+        self.bg = Some(bg);
+        self
+    }
+
+    pub const fn attrs(mut self, attrs: Attrs) -> Self {
+        self.attrs = self.attrs.union(attrs);
+        self
+    }
+
+    /// This style with `other` drawn over it
+    pub fn patch(self, other: Style) -> Style {
+        Style {
+            fg: other.fg.or(self.fg),
+            bg: other.bg.or(self.bg),
+            attrs: self.attrs.union(other.attrs),
+        }
+    }
+}
+
+/// Named styles of everything the TUI draws
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    /// Base of every cell, unset colours are the terminal defaults
+    pub text: Style,
+    /// Tree structure in front of each line
+    pub guides: Style,
+    /// Name of the executable in a command line
+    pub program: Style,
+    pub flag: Style,
+    pub path: Style,
+    /// Arguments which had to be quoted
+    pub quoted: Style,
+    pub annotation: Style,
+    /// Text of nodes which did not succeed
+    pub failed: Style,
+    /// Line of the selected node
+    pub selection: Style,
+    pub search_match: Style,
+    /// Status line and filter header
+    pub status: Style,
+    pub border: Style,
+    /// Border of popups and focused panes
+    pub border_focused: Style,
+    /// Cursor in the prompt line
+    pub cursor: Style,
+}
+
+pub const DARK: Theme = Theme {
+    text: Style::new().fg(Color::Indexed(255)).bg(Color::Indexed(234)),
+    guides: Style::new().fg(Color::Indexed(244)),
+    program: Style::new().attrs(Attrs::BOLD),
+    flag: Style::new().fg(Color::Indexed(110)),
+    path: Style::new().fg(Color::Indexed(150)),
+    quoted: Style::new().fg(Color::Indexed(180)),
+    annotation: Style::new().fg(Color::Indexed(244)),
+    failed: Style::new().fg(Color::Indexed(203)),
+    selection: Style::new().bg(Color::Indexed(236)),
+    search_match: Style::new().fg(Color::Indexed(16)).bg(Color::Indexed(178)),
+    status: Style::new().fg(Color::Indexed(255)).bg(Color::Indexed(236)),
+    border: Style::new().fg(Color::Indexed(244)),
+    border_focused: Style::new().fg(Color::Indexed(255)),
+    cursor: Style::new().bg(Color::Indexed(255)),
+};
+
+pub const LIGHT: Theme = Theme {
+    text: Style::new().fg(Color::Indexed(235)),
+    guides: Style::new().fg(Color::Indexed(245)),
+    program: Style::new().attrs(Attrs::BOLD),
+    flag: Style::new().fg(Color::Indexed(25)),
+    path: Style::new().fg(Color::Indexed(28)),
+    quoted: Style::new().fg(Color::Indexed(130)),
+    annotation: Style::new().fg(Color::Indexed(245)),
+    failed: Style::new().fg(Color::Indexed(160)),
+    selection: Style::new().bg(Color::Indexed(254)),
+    search_match: Style::new().fg(Color::Indexed(16)).bg(Color::Indexed(220)),
+    status: Style::new().fg(Color::Indexed(235)).bg(Color::Indexed(252)),
+    border: Style::new().fg(Color::Indexed(245)),
+    border_focused: Style::new().fg(Color::Indexed(235)),
+    cursor: Style::new().bg(Color::Indexed(235)),
+};
+
+pub const ANSI16: Theme = Theme {
+    text: Style::new(),
+    guides: Style::new().fg(Color::Basic(8)),
+    program: Style::new().attrs(Attrs::BOLD),
+    flag: Style::new().fg(Color::Basic(6)),
+    path: Style::new().fg(Color::Basic(2)),
+    quoted: Style::new().fg(Color::Basic(3)),
+    annotation: Style::new().fg(Color::Basic(8)),
+    failed: Style::new().fg(Color::Basic(1)),
+    selection: Style::new().fg(Color::Basic(0)).bg(Color::Basic(6)),
+    search_match: Style::new().fg(Color::Basic(0)).bg(Color::Basic(3)),
+    status: Style::new().fg(Color::Basic(0)).bg(Color::Basic(7)),
+    border: Style::new().fg(Color::Basic(8)),
+    border_focused: Style::new(),
+    cursor: Style::new().bg(Color::Basic(7)),
+};
+
+/// Only attributes, for terminals without colours and `NO_COLOR`
+pub const NONE: Theme = Theme {
+    text: Style::new(),
+    guides: Style::new().attrs(Attrs::DIM),
+    program: Style::new().attrs(Attrs::BOLD),
+    flag: Style::new(),
+    path: Style::new(),
+    quoted: Style::new().attrs(Attrs::UNDERLINE),
+    annotation: Style::new().attrs(Attrs::DIM),
+    failed: Style::new().attrs(Attrs::ITALIC),
+    selection: Style::new().attrs(Attrs::REVERSE),
+    search_match: Style::new().attrs(Attrs::UNDERLINE.union(Attrs::BOLD)),
+    status: Style::new().attrs(Attrs::REVERSE),
+    border: Style::new().attrs(Attrs::DIM),
+    border_focused: Style::new(),
+    cursor: Style::new().attrs(Attrs::REVERSE),
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StyleConfig {
+    fg: Option<String>,
+    bg: Option<String>,
+    #[serde(default)]
+    attrs: Vec<String>,
+}
+
+/// Theme file, a built-in base theme with some of its styles replaced
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeConfig {
+    base: Option<String>,
+    text: Option<StyleConfig>,
+    guides: Option<StyleConfig>,
+    program: Option<StyleConfig>,
+    flag: Option<StyleConfig>,
+    path: Option<StyleConfig>,
+    quoted: Option<StyleConfig>,
+    annotation: Option<StyleConfig>,
+    failed: Option<StyleConfig>,
+    selection: Option<StyleConfig>,
+    search_match: Option<StyleConfig>,
+    status: Option<StyleConfig>,
+    border: Option<StyleConfig>,
+    border_focused: Option<StyleConfig>,
+    cursor: Option<StyleConfig>,
+}
+
+impl StyleConfig {
+    fn to_style(&self) -> Result<Style> {
+        let color = |name: &Option<String>| match name {
+            Some(name) => Color::from_name(name)
+                .map(Some)
+                .ok_or_else(|| failure::format_err!("Unknown colour '{}'", name)),
+            None => Ok(None),
+        };
+        let mut attrs = Attrs::NONE;
+        for name in &self.attrs {
+            attrs = attrs.union(
+                Attrs::from_name(name)
+                    .ok_or_else(|| failure::format_err!("Unknown attribute '{}'", name))?,
+            );
+        }
+        Ok(Style {
+            fg: color(&self.fg)?,
+            bg: color(&self.bg)?,
+            attrs,
+        })
+    }
+}
+
+impl Theme {
+    pub fn from_name(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(DARK),
+            "light" => Some(LIGHT),
+            "16" => Some(ANSI16),
+            "none" => Some(NONE),
+            _ => None,
+        }
+    }
+
+    /// Style of a part of a node's line
+    pub fn span(&self, kind: SpanKind) -> Style {
+        match kind {
+            SpanKind::Text => Style::new(),
+            SpanKind::Program => self.program,
+            SpanKind::Flag => self.flag,
+            SpanKind::Path => self.path,
+            SpanKind::Quoted => self.quoted,
+        }
+    }
+
+    /// Reads a JSON theme file like
+    /// `{"base": "16", "selection": {"fg": "default", "bg": "blue", "attrs": ["bold"]}}`
+    pub fn from_reader<R: io::Read>(r: R) -> Result<Theme> {
+        let config: ThemeConfig = serde_json::from_reader(r)?;
+        let base = config.base.as_deref().unwrap_or("dark");
+        let mut theme =
+            Theme::from_name(base).ok_or_else(|| failure::format_err!("Unknown theme '{}'", base))?;
+
+        let styles = [
+            (&mut theme.text, &config.text),
+            (&mut theme.guides, &config.guides),
+            (&mut theme.program, &config.program),
+            (&mut theme.flag, &config.flag),
+            (&mut theme.path, &config.path),
+            (&mut theme.quoted, &config.quoted),
+            (&mut theme.annotation, &config.annotation),
+            (&mut theme.failed, &config.failed),
+            (&mut theme.selection, &config.selection),
+            (&mut theme.search_match, &config.search_match),
+            (&mut theme.status, &config.status),
+            (&mut theme.border, &config.border),
+            (&mut theme.border_focused, &config.border_focused),
+            (&mut theme.cursor, &config.cursor),
+        ];
+        for (style, config) in styles {
+            if let Some(config) = config {
+                *style = config.to_style()?;
+            }
+        }
+        Ok(theme)
+    }
+
+    /// Built-in theme `name` or otherwise the theme file at that path
+    pub fn load(name: &str) -> Result<Theme> {
+        match Theme::from_name(name) {
+            Some(theme) => Ok(theme),
+            None => Theme::from_reader(io::BufReader::new(fs::File::open(name)?)),
+        }
+    }
+
+    /// Theme file used without `--theme`, `$XDG_CONFIG_HOME/race/theme.json`
+    pub fn config_path() -> Option<path::PathBuf> {
+        let dir = match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+            Some(dir) => path::PathBuf::from(dir),
+            None => path::PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("race").join("theme.json"))
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        DARK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors() {
+        assert_eq!(Color::from_name("default"), Some(Color::Default));
+        assert_eq!(Color::from_name("red"), Some(Color::Basic(1)));
+        assert_eq!(Color::from_name("bright-white"), Some(Color::Basic(15)));
+        assert_eq!(Color::from_name("236"), Some(Color::Indexed(236)));
+        assert_eq!(Color::from_name("256"), None);
+        assert_eq!(Color::from_name("bright-"), None);
+
+        assert_eq!(Fg(Color::Basic(9)).to_string(), "\x1b[91m");
+        assert_eq!(Bg(Color::Basic(2)).to_string(), "\x1b[42m");
+        assert_eq!(Bg(Color::Default).to_string(), "\x1b[49m");
+        assert_eq!(Fg(Color::Indexed(203)).to_string(), "\x1b[38;5;203m");
+    }
+
+    #[test]
+    fn patch() {
+        let base = Style::new().fg(Color::Basic(7)).bg(Color::Basic(0));
+        let patched = base.patch(Style::new().bg(Color::Default));
+        assert_eq!(patched, Style::new().fg(Color::Basic(7)).bg(Color::Default));
+    }
+
+    #[test]
+    fn attrs() {
+        let set = |from, to| SetAttrs { from, to }.to_string();
+        let bold_dim = Attrs::BOLD.union(Attrs::DIM);
+        assert_eq!(set(Attrs::NONE, Attrs::NONE), "");
+        assert_eq!(set(Attrs::NONE, Attrs::BOLD), "\x1b[1m");
+        assert_eq!(set(Attrs::REVERSE, Attrs::UNDERLINE), "\x1b[27;4m");
+        assert_eq!(set(bold_dim, Attrs::NONE), "\x1b[22m");
+        // Turning off bold also turns off dim, which has to be restored
+        assert_eq!(set(bold_dim, Attrs::DIM), "\x1b[22;2m");
+
+        let style = Style::new().attrs(Attrs::BOLD).patch(Style::new().attrs(Attrs::ITALIC));
+        assert!(style.attrs.contains(Attrs::BOLD.union(Attrs::ITALIC)));
+        assert!(!style.attrs.contains(Attrs::DIM));
+    }
+
+    #[test]
+    fn theme_file() {
+        let json = r#"{"base": "16", "text": {"bg": "default"}, "selection": {"bg": "blue"},
+            "program": {"attrs": ["underline", "italic"]}}"#;
+        let theme = Theme::from_reader(json.as_bytes()).unwrap();
+        assert_eq!(theme.text, Style::new().bg(Color::Default));
+        assert_eq!(
+            theme.program,
+            Style::new().attrs(Attrs::UNDERLINE.union(Attrs::ITALIC))
+        );
+        assert_eq!(theme.selection, Style::new().bg(Color::Basic(4)));
+        assert_eq!(theme.failed, ANSI16.failed);
+
+        // Without a base the file starts from the dark theme
+        let theme = Theme::from_reader(r#"{"failed": {"fg": "9"}}"#.as_bytes()).unwrap();
+        assert_eq!(theme.guides, DARK.guides);
+
+        assert!(Theme::from_reader(r#"{"base": "pink"}"#.as_bytes()).is_err());
+        assert!(Theme::from_reader(r#"{"failed": {"fg": "pink"}}"#.as_bytes()).is_err());
+        assert!(Theme::from_reader(r#"{"unknown": {}}"#.as_bytes()).is_err());
+        assert!(Theme::from_reader(r#"{"failed": {"attrs": ["blink"]}}"#.as_bytes()).is_err());
+    }
+}
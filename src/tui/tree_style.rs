@@ -0,0 +1,83 @@
+/// Glyphs used to draw the tree structure in front of each line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeStyle {
+    /// Indentation below an ancestor which has further siblings
+    pub vertical: &'static str,
+    /// Indentation below an ancestor without further siblings
+    pub blank: &'static str,
+    /// Connector to a node which has further siblings
+    pub tee: &'static str,
+    /// Connector to the last child
+    pub elbow: &'static str,
+    /// Show `[+]`/`[-]` expand markers in front of each node
+    pub markers: bool,
+}
+
+pub const UNICODE: TreeStyle = TreeStyle {
+    vertical: "    │   ",
+    blank: "        ",
+    tee: "    ├── ",
+    elbow: "    └── ",
+    markers: true,
+};
+
+pub const ASCII: TreeStyle = TreeStyle {
+    vertical: "| ",
+    blank: "  ",
+    tee: "\\_ ",
+    elbow: "\\_ ",
+    markers: true,
+};
+
+pub const FOREST: TreeStyle = TreeStyle {
+    vertical: "    ",
+    blank: "    ",
+    tee: " \\_ ",
+    elbow: " \\_ ",
+    markers: true,
+};
+
+pub const INDENT: TreeStyle = TreeStyle {
+    vertical: "  ",
+    blank: "  ",
+    tee: "  ",
+    elbow: "  ",
+    markers: true,
+};
+
+impl TreeStyle {
+    pub fn from_name(name: &str) -> Option<TreeStyle> {
+        match name {
+            "unicode" => Some(UNICODE),
+            "ascii" => Some(ASCII),
+            "forest" => Some(FOREST),
+            "indent" => Some(INDENT),
+            _ => None,
+        }
+    }
+
+    pub fn marker(&self, expanded: bool) -> &'static str {
+        match (self.markers, expanded) {
+            (false, _) => "",
+            (true, true) => "[+] ",
+            (true, false) => "[-] ",
+        }
+    }
+
+    /// Indentation of continuation lines up to where the first line's text starts
+    pub fn continuation(&self, has_sibling: bool) -> String {
+        let indent = if has_sibling {
+            self.vertical
+        } else {
+            self.blank
+        };
+        let width = self.tee.chars().count() + self.marker(true).len();
+        format!("{:1$}", indent, width)
+    }
+}
+
+impl Default for TreeStyle {
+    fn default() -> Self {
+        UNICODE
+    }
+}
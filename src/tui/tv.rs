@@ -1,26 +1,377 @@
 use crate::tui;
+use crate::tui::binding::{self, Binding, HelpSection};
+use crate::tui::detail::DetailPane;
+use crate::tui::layout::{Direction, Size, Split};
+use crate::tui::search;
+use crate::tui::text;
+use crate::tui::theme::{self, Attrs, Fg, SetAttrs, Style, Theme};
+use crate::tui::tree_style::TreeStyle;
+use crate::tui::widget::{Popup, StatusBar, SCROLL_BINDINGS};
+pub use crate::util::{line_text, Span, SpanKind};
 use crate::util::Point;
 
+use termion::event::Key;
+use termion::style;
+
+use std::borrow::Cow;
 use std::cmp;
+use std::io;
+
+/// What siblings are ordered by, instead of the order they were added in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Pid,
+    Start,
+    Duration,
+    /// Number of nodes in the subtree
+    Size,
+    Status,
+    Name,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 6] = [
+        SortKey::Pid,
+        SortKey::Start,
+        SortKey::Duration,
+        SortKey::Size,
+        SortKey::Status,
+        SortKey::Name,
+    ];
+
+    pub fn from_name(name: &str) -> Option<SortKey> {
+        SortKey::ALL.iter().cloned().find(|key| key.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Pid => "pid",
+            SortKey::Start => "start",
+            SortKey::Duration => "duration",
+            SortKey::Size => "size",
+            SortKey::Status => "status",
+            SortKey::Name => "name",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
 
 pub trait Tree {
     type NodeIter: Iterator<Item = Vec<usize>>;
-    type LineIter: Iterator<Item = String>;
+    type LineIter: Iterator<Item = Vec<Span>>;
 
     fn size(&self) -> usize;
     fn next_sibling(&self, node: usize) -> Option<usize>;
 
     fn node_iter(&self) -> Self::NodeIter;
     fn line_iter(&self, node: usize) -> Self::LineIter;
+
+    /// Short summary appended to the node's first line, like its exit status and duration
+    fn annotation(&self, _node: usize) -> Option<String> {
+        None
+    }
+    fn failed(&self, _node: usize) -> bool {
+        false
+    }
+    /// Short description of the node for the status line, like its pid and program
+    fn describe(&self, _node: usize) -> Option<String> {
+        None
+    }
+    /// Everything known about the node, shown in the detail pane
+    fn details(&self, _node: usize) -> Vec<String> {
+        Vec::new()
+    }
+    /// Shell command running the node again, copied by the yank action
+    fn command(&self, _node: usize) -> Option<String> {
+        None
+    }
+    /// Orders siblings in `node_iter` and `next_sibling` by `sort`, or as added with `None`
+    fn set_sort(&mut self, _sort: Option<Sort>) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Search,
+    SearchBackward,
+    SearchNext,
+    SearchPrevious,
+    Filter,
+    ToggleExpand,
+    ToggleDetail,
+    ShowDetails,
+    ScrollLeft,
+    Down,
+    Up,
+    ScrollRight,
+    ScrollBegin,
+    ScrollEnd,
+    First,
+    Last,
+    QuarterPageDown,
+    QuarterPageUp,
+    PageDown,
+    PageUp,
+    ClearHighlight,
+    FoldPrefix,
+    Yank,
+    YankSubtree,
+    Sort,
+    ReverseSort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fold {
+    CollapseAll,
+    ExpandAll,
+    ExpandRecursive,
+    CollapseSiblings,
+}
+
+/// Commands following the `z` prefix
+const FOLD_BINDINGS: &[Binding<Fold>] = &[
+    Binding {
+        key: Key::Char('M'),
+        action: Fold::CollapseAll,
+        help: "Collapse all, with count show that many levels",
+    },
+    Binding {
+        key: Key::Char('R'),
+        action: Fold::ExpandAll,
+        help: "Expand all",
+    },
+    Binding {
+        key: Key::Char('o'),
+        action: Fold::ExpandRecursive,
+        help: "Expand selected node recursively",
+    },
+    Binding {
+        key: Key::Char('c'),
+        action: Fold::CollapseSiblings,
+        help: "Collapse siblings of selected node",
+    },
+];
+
+const BINDINGS: &[Binding<Action>] = &[
+    Binding {
+        key: Key::Char('/'),
+        action: Action::Search,
+        help: "Search forward",
+    },
+    Binding {
+        key: Key::Char('#'),
+        action: Action::SearchBackward,
+        help: "Search backward, not ? which shows this help",
+    },
+    Binding {
+        key: Key::Char('n'),
+        action: Action::SearchNext,
+        help: "Next match",
+    },
+    Binding {
+        key: Key::Char('N'),
+        action: Action::SearchPrevious,
+        help: "Previous match",
+    },
+    Binding {
+        key: Key::Char('f'),
+        action: Action::Filter,
+        help: "Filter nodes",
+    },
+    Binding {
+        key: Key::Char(' '),
+        action: Action::ToggleExpand,
+        help: "Expand or collapse node",
+    },
+    Binding {
+        key: Key::Char('i'),
+        action: Action::ToggleDetail,
+        help: "Show or hide details pane",
+    },
+    Binding {
+        key: Key::Char('\n'),
+        action: Action::ShowDetails,
+        help: "Show details in popup",
+    },
+    Binding {
+        key: Key::Char('h'),
+        action: Action::ScrollLeft,
+        help: "Scroll left",
+    },
+    Binding {
+        key: Key::Left,
+        action: Action::ScrollLeft,
+        help: "Scroll left",
+    },
+    Binding {
+        key: Key::Char('j'),
+        action: Action::Down,
+        help: "Select next line",
+    },
+    Binding {
+        key: Key::Down,
+        action: Action::Down,
+        help: "Select next line",
+    },
+    Binding {
+        key: Key::Ctrl('n'),
+        action: Action::Down,
+        help: "Select next line",
+    },
+    Binding {
+        key: Key::Char('k'),
+        action: Action::Up,
+        help: "Select previous line",
+    },
+    Binding {
+        key: Key::Up,
+        action: Action::Up,
+        help: "Select previous line",
+    },
+    Binding {
+        key: Key::Ctrl('p'),
+        action: Action::Up,
+        help: "Select previous line",
+    },
+    Binding {
+        key: Key::Char('l'),
+        action: Action::ScrollRight,
+        help: "Scroll right",
+    },
+    Binding {
+        key: Key::Right,
+        action: Action::ScrollRight,
+        help: "Scroll right",
+    },
+    Binding {
+        key: Key::Char('0'),
+        action: Action::ScrollBegin,
+        help: "Scroll to line start",
+    },
+    Binding {
+        key: Key::Char('$'),
+        action: Action::ScrollEnd,
+        help: "Scroll to line end",
+    },
+    Binding {
+        key: Key::Char('g'),
+        action: Action::First,
+        help: "Select first line",
+    },
+    Binding {
+        key: Key::Home,
+        action: Action::First,
+        help: "Select first line",
+    },
+    Binding {
+        key: Key::Char('G'),
+        action: Action::Last,
+        help: "Select last line",
+    },
+    Binding {
+        key: Key::End,
+        action: Action::Last,
+        help: "Select last line",
+    },
+    Binding {
+        key: Key::Char('d'),
+        action: Action::QuarterPageDown,
+        help: "Move selection a quarter page down",
+    },
+    Binding {
+        key: Key::Char('u'),
+        action: Action::QuarterPageUp,
+        help: "Move selection a quarter page up",
+    },
+    Binding {
+        key: Key::PageDown,
+        action: Action::PageDown,
+        help: "Move selection a page down",
+    },
+    Binding {
+        key: Key::Ctrl('f'),
+        action: Action::PageDown,
+        help: "Move selection a page down",
+    },
+    Binding {
+        key: Key::PageUp,
+        action: Action::PageUp,
+        help: "Move selection a page up",
+    },
+    Binding {
+        key: Key::Ctrl('b'),
+        action: Action::PageUp,
+        help: "Move selection a page up",
+    },
+    Binding {
+        key: Key::Esc,
+        action: Action::ClearHighlight,
+        help: "Clear search highlight",
+    },
+    Binding {
+        key: Key::Char('z'),
+        action: Action::FoldPrefix,
+        help: "Fold command, see below",
+    },
+    Binding {
+        key: Key::Char('y'),
+        action: Action::Yank,
+        help: "Copy command line",
+    },
+    Binding {
+        key: Key::Char('Y'),
+        action: Action::YankSubtree,
+        help: "Copy subtree as shown",
+    },
+    Binding {
+        key: Key::Char('s'),
+        action: Action::Sort,
+        help: "Sort children by the next key",
+    },
+    Binding {
+        key: Key::Char('S'),
+        action: Action::ReverseSort,
+        help: "Reverse sort order",
+    },
+];
+
+#[derive(Debug, Clone)]
+enum PromptKind {
+    /// Node selected and folds when the search started, to return to on cancel
+    Search {
+        backward: bool,
+        origin: usize,
+        expanded: Vec<bool>,
+    },
+    /// Filter active when the prompt was opened
+    Filter { previous: Option<String> },
 }
 
 #[derive(Debug)]
 pub struct TreeView<T: Tree> {
     tree: T,
     lines: Vec<String>,
+    style: TreeStyle,
 
     expanded: Vec<bool>,
+    visible: Option<Vec<bool>>,
+    /// Paths of all nodes in the order they are shown, and the index of each node's path
+    paths: Vec<Vec<usize>>,
+    path_index: Vec<usize>,
+    /// Last child of each node that is shown with the current filter, if there is one
+    last_visible_child: Vec<Option<usize>>,
     lookup: Vec<usize>,
+    text_start: Vec<usize>,
+    /// Kinds of the spans of each line, with the column after the tree guides each span
+    /// ends at
+    kinds: Vec<Vec<(usize, SpanKind)>>,
+    /// Annotation shown after each line, kept out of the line text so searching and
+    /// filtering never match it
+    annotations: Vec<Option<String>>,
 
     size: Point,
     data_size: Point,
@@ -28,6 +379,22 @@ pub struct TreeView<T: Tree> {
     scroll_max: Point,
     selected_line: i32,
 
+    prompt: Option<PromptKind>,
+    highlight: Option<String>,
+    last_search: Option<(String, bool)>,
+    filter: Option<String>,
+    /// Number of nodes matching the filter
+    filter_matches: usize,
+    sort: Option<Sort>,
+
+    detail: Option<DetailPane>,
+    /// Where the tree lines were last drawn, to map mouse clicks
+    tree_rect: Option<tui::Rect>,
+
+    /// Numeric prefix typed so far
+    count: Option<usize>,
+    fold_prefix: bool,
+
     dirty: bool,
 }
 
@@ -37,9 +404,17 @@ impl<T: Tree> TreeView<T> {
         let mut tv = TreeView {
             tree,
             lines: Vec::new(),
+            style: TreeStyle::default(),
 
             expanded: vec![true; size],
+            visible: None,
+            paths: Vec::new(),
+            path_index: vec![0; size],
+            last_visible_child: Vec::new(),
             lookup: Vec::new(),
+            text_start: Vec::new(),
+            kinds: Vec::new(),
+            annotations: Vec::new(),
 
             size: Point::new(0, 0),
             data_size: Point::new(0, 0),
@@ -47,20 +422,116 @@ impl<T: Tree> TreeView<T> {
             scroll_max: Point::new(0, 0),
             selected_line: 0,
 
+            prompt: None,
+            highlight: None,
+            last_search: None,
+            filter: None,
+            filter_matches: 0,
+            sort: None,
+
+            detail: None,
+            tree_rect: None,
+
+            count: None,
+            fold_prefix: false,
+
             dirty: false,
         };
+        tv.fetch_paths();
         tv.fetch_lines();
         tv
     }
 
+    pub fn set_style(&mut self, style: TreeStyle) {
+        self.style = style;
+        self.fetch_lines();
+        self.dirty = true;
+    }
+
+    /// Orders siblings by `sort` without changing the tree, keeps the selected node
+    pub fn set_sort(&mut self, sort: Option<Sort>) {
+        let selected = self.lookup.get(self.selected_line as usize).cloned();
+        self.sort = sort;
+        self.tree.set_sort(sort);
+        self.fetch_paths();
+        self.fetch_lines();
+        if let Some(node) = selected {
+            self.reveal_node(node);
+        }
+        self.dirty = true;
+    }
+
+    /// Switches to the next sort key, after the last one back to the recorded order
+    fn cycle_sort(&mut self) -> tui::Response {
+        let descending = self.sort.is_some_and(|s| s.descending);
+        let key = match self.sort {
+            None => Some(SortKey::ALL[0]),
+            Some(sort) => SortKey::ALL
+                .iter()
+                .skip_while(|&&key| key != sort.key)
+                .nth(1)
+                .cloned(),
+        };
+        self.set_sort(key.map(|key| Sort { key, descending }));
+        tui::Response::Message(self.sort_description())
+    }
+
+    fn reverse_sort(&mut self) -> tui::Response {
+        match self.sort {
+            Some(sort) => {
+                self.set_sort(Some(Sort {
+                    descending: !sort.descending,
+                    ..sort
+                }));
+                tui::Response::Message(self.sort_description())
+            }
+            None => tui::Response::Message("Not sorted, press s to sort".to_string()),
+        }
+    }
+
+    fn sort_description(&self) -> String {
+        match self.sort {
+            Some(Sort {
+                key,
+                descending: false,
+            }) => format!("sort: {}", key.name()),
+            Some(Sort {
+                key,
+                descending: true,
+            }) => format!("sort: {} desc", key.name()),
+            None => "Recorded order".to_string(),
+        }
+    }
+
+    fn fetch_paths(&mut self) {
+        self.paths = self.tree.node_iter().collect();
+        for (idx, path) in self.paths.iter().enumerate() {
+            self.path_index[*path.last().unwrap()] = idx;
+        }
+        self.fetch_last_visible_children();
+    }
+
+    fn fetch_last_visible_children(&mut self) {
+        let visible = match &self.visible {
+            Some(visible) => visible,
+            None => return self.last_visible_child.clear(),
+        };
+        let mut last = vec![None; self.tree.size()];
+        for path in self.paths.iter().filter(|p| p.len() > 1) {
+            let id = *path.last().unwrap();
+            if visible[id] {
+                last[path[path.len() - 2]] = Some(id);
+            }
+        }
+        self.last_visible_child = last;
+    }
+
     fn fetch_lines(&mut self) {
         self.lines = TVLineIter::new(self).collect();
 
         self.data_size.y = self.lines.len() as i32;
-        self.data_size.x = self
-            .lines
-            .iter()
-            .map(|s| s.chars().count())
+        self.data_size.x = (0..self.lines.len())
+            .map(|line| text::width(&self.annotated_line(line)))
             .max()
             .unwrap_or(0) as i32;
 
@@ -72,6 +543,8 @@ impl<T: Tree> TreeView<T> {
             self.size = size;
             self.update_scroll_max();
             self.scroll(0, 0);
+            // Keep the selection visible when the view shrinks
+            self.handle_scrolloff();
         }
     }
 
@@ -153,51 +626,583 @@ impl<T: Tree> TreeView<T> {
         }
     }
 
-    fn toggle_expand(&mut self) {
+    fn toggle_expand(&mut self) -> tui::Response {
         let id: usize = self.lookup[self.selected_line as usize];
-        if self.expanded[id] {
-            self.expanded[id] = false;
-        } else {
-            self.expanded[id] = true;
+        // Keep the marker unchanged if no lines would be shown or hidden
+        if !self.has_visible_children(id) {
+            return tui::Response::Message("Nothing to expand or collapse".to_string());
+        }
+        self.expanded[id] = !self.expanded[id];
+        self.fetch_lines();
+
+        self.dirty = true;
+        tui::Response::None
+    }
+
+    /// Left and right text of the status line
+    fn status(&self) -> (String, String) {
+        let mut left = Vec::new();
+        if let Some(&node) = self.lookup.get(self.selected_line as usize) {
+            left.extend(self.tree.describe(node));
+        }
+        if let Some(pattern) = &self.highlight {
+            left.push(format!("/{}", pattern));
+        }
+        if let Some(filter) = &self.filter {
+            left.push(format!("filter: {}", filter));
+        }
+        if self.sort.is_some() {
+            left.push(self.sort_description());
+        }
+        if self.count.is_some() || self.fold_prefix {
+            let count = self.count.map(|c| c.to_string()).unwrap_or_default();
+            left.push(format!(
+                "{}{}",
+                count,
+                if self.fold_prefix { "z" } else { "" }
+            ));
+        }
+
+        let right = format!(
+            "{}/{}  {} nodes  ?:help q:quit",
+            self.selected_line + 1,
+            self.data_size.y,
+            self.tree.size()
+        );
+        (left.join("  "), right)
+    }
+
+    fn toggle_detail(&mut self) {
+        self.detail = match self.detail {
+            Some(_) => None,
+            None => Some(DetailPane::new()),
+        };
+        self.dirty = true;
+    }
+
+    /// Selects the line at `p`, relative to the tree's rect, toggling it if the marker was hit
+    fn click(&mut self, p: Point) -> tui::Response {
+        let line = p.y + self.scroll.y;
+        if line >= self.data_size.y {
+            return tui::Response::None;
+        }
+        self.select(line - self.selected_line);
+        self.dirty = true;
+
+        if self.on_marker(line as usize, p.x + self.scroll.x) {
+            return self.toggle_expand();
+        }
+        tui::Response::None
+    }
+
+    /// Whether column `x` of `line` shows the node's `[+]`/`[-]` marker
+    fn on_marker(&self, line: usize, x: i32) -> bool {
+        if !self.style.markers || (line > 0 && self.lookup[line - 1] == self.lookup[line]) {
+            return false;
+        }
+        let marker = self.style.marker(true);
+        let start = (self.text_column(line) - text::width(marker)) as i32;
+        x >= start && x < start + text::width(marker.trim_end()) as i32
+    }
+
+    /// Column after the tree guides of `line`
+    fn text_column(&self, line: usize) -> usize {
+        text::width(&self.lines[line][..self.text_start[line]])
+    }
+
+    /// Shows only nodes for which `predicate` holds, and their ancestors
+    ///
+    /// Passing `None` shows all nodes again. Returns the number of nodes `predicate` holds for.
+    pub fn set_visibility<F: Fn(&T, usize) -> bool>(&mut self, predicate: Option<F>) -> usize {
+        let selected = self.lookup.get(self.selected_line as usize).cloned();
+
+        let mut matches = self.tree.size();
+        self.visible = predicate.map(|predicate| {
+            matches = 0;
+            let mut visible = vec![false; self.tree.size()];
+            for path in &self.paths {
+                if predicate(&self.tree, *path.last().unwrap()) {
+                    matches += 1;
+                    for &id in path {
+                        visible[id] = true;
+                    }
+                }
+            }
+            visible
+        });
+        self.fetch_last_visible_children();
+        self.fetch_lines();
+        self.dirty = true;
+
+        let line = selected
+            .and_then(|node| self.lookup.iter().position(|&id| id == node))
+            .unwrap_or(0);
+        self.selected_line = 0;
+        self.select(line as i32);
+        self.scroll(0, 0);
+        self.handle_scrolloff();
+        matches
+    }
+
+    fn is_visible(&self, id: usize) -> bool {
+        self.visible.as_ref().is_none_or(|visible| visible[id])
+    }
+
+    /// Whether `id` has children which are shown when it is expanded
+    fn has_visible_children(&self, id: usize) -> bool {
+        if self.visible.is_some() {
+            return self.last_visible_child[id].is_some();
+        }
+        let idx = self.path_index[id];
+        self.paths
+            .get(idx + 1)
+            .is_some_and(|next| next.len() > self.paths[idx].len())
+    }
+
+    /// Whether a shown sibling follows the shown node `id`
+    fn has_visible_sibling(&self, id: usize) -> bool {
+        if self.visible.is_none() {
+            return self.tree.next_sibling(id).is_some();
+        }
+        let path = &self.paths[self.path_index[id]];
+        path.len() > 1 && self.last_visible_child[path[path.len() - 2]] != Some(id)
+    }
+
+    fn set_filter(&mut self, pattern: Option<String>) {
+        self.filter_matches = match &pattern {
+            Some(pattern) => self.set_visibility(Some(|tree: &T, id| {
+                tree.line_iter(id)
+                    .any(|l| search::is_match(&line_text(&l), pattern))
+            })),
+            None => self.set_visibility(None::<fn(&T, usize) -> bool>),
+        };
+        self.filter = pattern;
+    }
+
+    fn handle_filter_prompt(
+        &mut self,
+        input: &str,
+        ev: tui::PromptEvent,
+        previous: Option<String>,
+    ) -> tui::Response {
+        use crate::tui::PromptEvent::*;
+
+        let filter = match ev {
+            Changed | Submitted if input.is_empty() => None,
+            Changed | Submitted => Some(input.to_string()),
+            Cancelled => previous,
+        };
+        self.set_filter(filter);
+        tui::Response::None
+    }
+
+    /// Shows `levels` levels of the tree, collapsing all nodes below
+    pub fn set_fold_depth(&mut self, levels: usize) {
+        let selected = self.selected_node();
+        self.fold_levels(levels);
+        self.refold(selected);
+    }
+
+    fn fold_levels(&mut self, levels: usize) {
+        for path in self.tree.node_iter() {
+            self.expanded[*path.last().unwrap()] = path.len() < levels;
+        }
+    }
+
+    fn fold(&mut self, fold: Fold, count: Option<usize>) -> tui::Response {
+        let selected = self.selected_node();
+        match fold {
+            Fold::CollapseAll => self.fold_levels(count.unwrap_or(1)),
+            Fold::ExpandAll => self.fold_levels(usize::MAX),
+            Fold::ExpandRecursive => {
+                for path in self.tree.node_iter().filter(|p| p.contains(&selected)) {
+                    self.expanded[*path.last().unwrap()] = true;
+                }
+            }
+            Fold::CollapseSiblings => {
+                let parent = self
+                    .tree
+                    .node_iter()
+                    .find(|p| *p.last().unwrap() == selected)
+                    .and_then(|p| p.iter().rev().nth(1).cloned());
+                let siblings: Vec<usize> = self
+                    .tree
+                    .node_iter()
+                    .filter(|p| p.len() >= 2 && Some(p[p.len() - 2]) == parent)
+                    .map(|p| *p.last().unwrap())
+                    .filter(|&id| id != selected)
+                    .collect();
+                for &id in &siblings {
+                    self.expanded[id] = false;
+                }
+                if siblings.is_empty() {
+                    return tui::Response::Message("No siblings to collapse".to_string());
+                }
+            }
         }
+        self.refold(selected);
+        tui::Response::None
+    }
+
+    /// Updates the lines after folds changed, keeping `node` or its closest shown ancestor selected
+    fn refold(&mut self, node: usize) {
+        let path = self
+            .tree
+            .node_iter()
+            .find(|p| *p.last().unwrap() == node)
+            .unwrap_or_default();
         self.fetch_lines();
+        self.dirty = true;
+
+        let line = path
+            .iter()
+            .rev()
+            .find_map(|id| self.lookup.iter().position(|l| l == id))
+            .unwrap_or(0);
+        self.select(line as i32 - self.selected_line);
+        self.scroll(0, 0);
+        self.handle_scrolloff();
+    }
 
+    fn selected_node(&self) -> usize {
+        self.lookup[self.selected_line as usize]
+    }
+
+    /// Lines of `node` and its shown descendants, without the guides leading to it
+    fn subtree_text(&self, node: usize) -> String {
+        let first = self.lookup.iter().position(|&id| id == node).unwrap();
+        let indent = self.lines[first][..self.text_start[first]].chars().count()
+            - self.style.marker(self.expanded[node]).chars().count();
+        // Paths of the node and its descendants all pass through the node at its depth
+        let depth = self.paths[self.path_index[node]].len();
+        let in_subtree = |id: usize| self.paths[self.path_index[id]].get(depth - 1) == Some(&node);
+
+        let mut text = String::new();
+        for (l, &id) in self.lines[first..].iter().zip(&self.lookup[first..]) {
+            if !in_subtree(id) {
+                break;
+            }
+            text.extend(l.chars().skip(indent));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Expands all ancestors of the node at the end of `path` and selects it
+    fn reveal(&mut self, path: &[usize]) {
+        let node = *path.last().unwrap();
+        let collapsed: Vec<usize> = path[..path.len() - 1]
+            .iter()
+            .cloned()
+            .filter(|&id| !self.expanded[id])
+            .collect();
+        if !collapsed.is_empty() {
+            for id in collapsed {
+                self.expanded[id] = true;
+            }
+            self.fetch_lines();
+            self.dirty = true;
+        }
+
+        if let Some(line) = self.lookup.iter().position(|&id| id == node) {
+            self.select(line as i32 - self.selected_line);
+        }
+    }
+
+    fn reveal_node(&mut self, node: usize) {
+        let path = self.paths[self.path_index[node]].clone();
+        self.reveal(&path);
+    }
+
+    /// Selects the next node after `from` with a line matching `pattern`, wrapping around
+    fn search(&mut self, pattern: &str, backward: bool, from: usize) -> bool {
+        let count = self.paths.len();
+        let pos = self.path_index[from];
+        let found = (1..=count)
+            .map(|step| match backward {
+                false => (pos + step) % count,
+                true => (pos + count - step) % count,
+            })
+            .find(|&idx| {
+                let id = *self.paths[idx].last().unwrap();
+                self.is_visible(id)
+                    && self
+                        .tree
+                        .line_iter(id)
+                        .any(|l| search::is_match(&line_text(&l), pattern))
+            })
+            .map(|idx| self.paths[idx].clone());
+
+        match found {
+            Some(path) => {
+                self.reveal(&path);
+                self.show_match(pattern);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Scrolls horizontally so the first match in the selected line is visible
+    fn show_match(&mut self, pattern: &str) {
+        let line = self.selected_line as usize;
+        let text_start = self.text_column(line);
+        let text = &self.lines[line][self.text_start[line]..];
+        if let Some(&(start, len)) = search::find_matches(text, pattern).first() {
+            let (start, end) = (
+                (text_start + text::char_column(text, start)) as i32,
+                (text_start + text::char_column(text, start + len)) as i32,
+            );
+            if start < self.scroll.x || end > self.scroll.x + self.size.x {
+                self.scroll(start - self.size.x / 4 - self.scroll.x, 0);
+            }
+        }
+    }
+
+    fn search_next(&mut self, reverse: bool) -> tui::Response {
+        let (pattern, backward) = match &self.last_search {
+            Some((pattern, backward)) => (pattern.clone(), *backward != reverse),
+            None => return tui::Response::Message("No previous search".to_string()),
+        };
+        self.highlight = Some(pattern.clone());
+        self.dirty = true;
+
+        if self.search(&pattern, backward, self.selected_node()) {
+            tui::Response::None
+        } else {
+            tui::Response::Message(format!("Pattern not found: {}", pattern))
+        }
+    }
+
+    fn open_search(&mut self, backward: bool) -> tui::Response {
+        self.prompt = Some(PromptKind::Search {
+            backward,
+            origin: self.selected_node(),
+            expanded: self.expanded.clone(),
+        });
+        tui::Response::Prompt(if backward { "#" } else { "/" }.to_string())
+    }
+
+    fn handle_search_prompt(
+        &mut self,
+        input: &str,
+        ev: tui::PromptEvent,
+        backward: bool,
+        origin: usize,
+        expanded: &[bool],
+    ) -> tui::Response {
+        use crate::tui::PromptEvent::*;
+
+        // Only the ancestors of the current match stay expanded
+        if self.expanded[..] != *expanded {
+            self.expanded.copy_from_slice(expanded);
+            self.fetch_lines();
+        }
         self.dirty = true;
+        match ev {
+            Changed => {
+                self.reveal_node(origin);
+                if input.is_empty() {
+                    self.highlight = None;
+                } else {
+                    self.highlight = Some(input.to_string());
+                    self.search(input, backward, origin);
+                }
+                tui::Response::None
+            }
+            Submitted => {
+                self.reveal_node(origin);
+                if !input.is_empty() {
+                    self.last_search = Some((input.to_string(), backward));
+                } else if let Some((_, last_backward)) = self.last_search.as_mut() {
+                    *last_backward = backward;
+                }
+                self.search_next(false)
+            }
+            Cancelled => {
+                self.reveal_node(origin);
+                self.highlight = self.last_search.as_ref().map(|(p, _)| p.clone());
+                tui::Response::None
+            }
+        }
+    }
+
+    /// Text of `line` followed by its annotation, if it has one
+    fn annotated_line(&self, line: usize) -> Cow<'_, str> {
+        match &self.annotations[line] {
+            Some(annotation) => Cow::Owned(format!("{}  {}", self.lines[line], annotation)),
+            None => Cow::Borrowed(&self.lines[line]),
+        }
     }
 
-    pub fn gen_lines(&mut self) -> Vec<String> {
-        TVLineIter::new(self).collect()
+    /// Styles of the guides, arguments and annotation of `line`, as pairs of the column each
+    /// one ends at and the style
+    fn line_spans(&self, line: usize, theme: &Theme) -> Vec<(usize, Style)> {
+        let text_start = self.text_column(line);
+        let failed = if self.tree.failed(self.lookup[line]) {
+            theme.failed
+        } else {
+            Style::new()
+        };
+
+        let mut spans = vec![(text_start, theme.guides)];
+        spans.extend(
+            self.kinds[line]
+                .iter()
+                .map(|&(end, kind)| (text_start + end, theme.span(kind).patch(failed))),
+        );
+        // Only the first line of a node has an annotation after its text
+        spans.push((text::width(&self.annotated_line(line)), theme.annotation));
+        spans
+    }
+
+    /// Writes all lines, optionally followed by their annotations and highlighted with the
+    /// colours of the 16 colour theme
+    pub fn write_lines<W: io::Write>(
+        &mut self,
+        w: &mut W,
+        colored: bool,
+        annotated: bool,
+    ) -> io::Result<()> {
+        self.fetch_lines();
+        for idx in 0..self.lines.len() {
+            let l = if annotated {
+                self.annotated_line(idx)
+            } else {
+                Cow::Borrowed(self.lines[idx].as_str())
+            };
+            if !colored {
+                writeln!(w, "{}", l)?;
+                continue;
+            }
+
+            let mut graphemes = text::graphemes(&l).peekable();
+            let mut x = 0;
+            for (end, style) in self.line_spans(idx, &theme::ANSI16) {
+                let mut text = String::new();
+                while let Some((g, width)) = graphemes.next_if(|_| x < end) {
+                    text.push_str(g);
+                    x += width;
+                }
+                if text.is_empty() {
+                    continue;
+                }
+                if let Some(fg) = style.fg {
+                    write!(w, "{}", Fg(fg))?;
+                }
+                write!(
+                    w,
+                    "{}{}{}",
+                    SetAttrs {
+                        from: Attrs::NONE,
+                        to: style.attrs
+                    },
+                    text,
+                    style::Reset
+                )?;
+            }
+            writeln!(w)?;
+        }
+
+        Ok(())
     }
 }
 
 impl<T: Tree> tui::Draw for TreeView<T> {
     fn draw(&mut self, rect: &tui::Rect, frame: &mut tui::Frame) {
-        self.update_size(rect.max - rect.min + Point::new(1, 1));
-
+        let (rect, status_rect) = Split::new(Direction::Vertical, Size::Last(1)).split(rect);
+        let (left, right) = self.status();
+        StatusBar::new(left, right).draw(&status_rect, frame);
+
+        let rect = &match &mut self.detail {
+            Some(detail) => {
+                let (tree_rect, detail_rect) =
+                    Split::new(Direction::Horizontal, Size::Percent(60)).split(&rect);
+                if let Some(&node) = self.lookup.get(self.selected_line as usize) {
+                    if detail.node() != Some(node) {
+                        detail.set_lines(node, self.tree.details(node));
+                    }
+                }
+                frame.clipped(&detail_rect, |frame| {
+                    tui::Draw::draw(detail, &detail_rect, frame)
+                });
+                tree_rect
+            }
+            None => rect,
+        };
         frame.clear_rect(rect);
-        for (y, l) in self
-            .lines
-            .iter()
-            .skip(self.scroll.y as usize)
-            .take(self.size.y as usize)
-            .enumerate()
-        {
-            for (x, c) in l
-                .chars()
-                .skip(self.scroll.x as usize)
-                .take(self.size.x as usize)
-                .enumerate()
+
+        let rect = &match &self.filter {
+            Some(filter) if rect.max.y > rect.min.y => {
+                StatusBar::new(
+                    format!("Filter: {}", filter),
+                    format!("{} matching", self.filter_matches),
+                )
+                .draw(rect, frame);
+                Split::new(Direction::Vertical, Size::First(1))
+                    .split(rect)
+                    .1
+            }
+            _ => *rect,
+        };
+        self.update_size(rect.max - rect.min + Point::new(1, 1));
+        self.tree_rect = Some(*rect);
+
+        let theme = *frame.theme();
+        // Wide glyphs in the last column must not spill into the detail pane
+        frame.clipped(rect, |frame| {
+            for y in (0..self.lines.len())
+                .skip(self.scroll.y as usize)
+                .take(self.size.y as usize)
             {
-                let p = Point::new(x as i32, y as i32);
-                assert!(p.x <= rect.max.x);
-                assert!(p.y <= rect.max.y);
-                frame.add(tui::Cell::new(rect.min + p, c));
+                let spans = self.line_spans(y, &theme);
+                let mut x = 0;
+                for (g, width) in text::graphemes(&self.annotated_line(y)) {
+                    let style = spans
+                        .iter()
+                        .find(|(end, _)| x < *end)
+                        .map_or(theme.text, |(_, style)| *style);
+                    let p = Point::new(x as i32 - self.scroll.x, y as i32 - self.scroll.y);
+                    x += width;
+                    if p.x >= self.size.x {
+                        break;
+                    } else if p.x >= 0 {
+                        frame.print(rect.min + p, g, style);
+                    } else if p.x + width as i32 > 0 {
+                        // Wide glyph cut by the left edge
+                        frame.put(rect.min + Point::new(0, p.y), ' ', style);
+                    }
+                }
             }
-        }
+        });
 
         for p in rect.points() {
-            if p.y == self.selected_line - self.scroll.y {
-                frame.cell_mut(p).bg = tui::LIGHT_GREY;
+            if p.y - rect.min.y == self.selected_line - self.scroll.y {
+                frame.set_style(p, theme.selection);
+            }
+        }
+
+        if let Some(pattern) = &self.highlight {
+            for (y, l) in self
+                .lines
+                .iter()
+                .enumerate()
+                .skip(self.scroll.y as usize)
+                .take(self.size.y as usize)
+            {
+                let text_start = self.text_column(y);
+                let text = &l[self.text_start[y]..];
+                for (start, len) in search::find_matches(text, pattern) {
+                    let columns = text_start + text::char_column(text, start)
+                        ..text_start + text::char_column(text, start + len);
+                    for x in columns {
+                        let x = x as i32 - self.scroll.x;
+                        if x >= 0 && x < self.size.x {
+                            let p = rect.min + Point::new(x, y as i32 - self.scroll.y);
+                            frame.set_style(p, theme.search_match);
+                        }
+                    }
+                }
             }
         }
 
@@ -205,44 +1210,178 @@ impl<T: Tree> tui::Draw for TreeView<T> {
     }
 
     fn dirty(&self) -> bool {
-        self.dirty
+        self.dirty || self.detail.as_ref().is_some_and(tui::Draw::dirty)
     }
 }
 
 impl<T: Tree> tui::Client for TreeView<T> {
-    fn handle_char(&mut self, c: char) {
-        match c {
-            ' ' => self.toggle_expand(),
+    fn handle_key(&mut self, key: Key) -> tui::Response {
+        // Numeric prefix, a leading zero is bound to scrolling instead
+        if let Key::Char(c @ '0'..='9') = key {
+            if c != '0' || self.count.is_some() {
+                let digit = c.to_digit(10).unwrap() as usize;
+                self.count = Some(
+                    self.count
+                        .unwrap_or(0)
+                        .saturating_mul(10)
+                        .saturating_add(digit),
+                );
+                self.dirty = true;
+                return tui::Response::None;
+            }
+        }
+        let count = self.count.take();
+        if self.fold_prefix {
+            self.fold_prefix = false;
+            self.dirty = true;
+            return match binding::lookup(FOLD_BINDINGS, key) {
+                Some(fold) => self.fold(fold, count),
+                None => tui::Response::None,
+            };
+        }
+        // Repeat count for movements
+        let repeat = cmp::min(count.unwrap_or(1), i32::MAX as usize) as i32;
+
+        let action = match binding::lookup(BINDINGS, key) {
+            Some(action) => action,
+            None => {
+                if count.is_some() {
+                    self.dirty = true;
+                }
+                return tui::Response::None;
+            }
+        };
+
+        match action {
+            Action::Search => return self.open_search(false),
+            Action::SearchBackward => return self.open_search(true),
+            Action::SearchNext => return self.search_next(false),
+            Action::SearchPrevious => return self.search_next(true),
+            Action::Filter => {
+                self.prompt = Some(PromptKind::Filter {
+                    previous: self.filter.clone(),
+                });
+                return tui::Response::Prompt("filter: ".to_string());
+            }
+
+            Action::ToggleExpand => return self.toggle_expand(),
+            Action::ToggleDetail => self.toggle_detail(),
+            Action::ShowDetails => {
+                if let Some(&node) = self.lookup.get(self.selected_line as usize) {
+                    let popup = Popup::new("Details", self.tree.details(node));
+                    return tui::Response::Popup(popup);
+                }
+            }
+
+            Action::Yank => {
+                if let Some(&node) = self.lookup.get(self.selected_line as usize) {
+                    return match self.tree.command(node) {
+                        Some(command) => tui::Response::Yank(command),
+                        None => tui::Response::Message("No command recorded".to_string()),
+                    };
+                }
+            }
+            Action::YankSubtree => {
+                if let Some(&node) = self.lookup.get(self.selected_line as usize) {
+                    return tui::Response::Yank(self.subtree_text(node));
+                }
+            }
+
+            Action::Sort => return self.cycle_sort(),
+            Action::ReverseSort => return self.reverse_sort(),
 
-            'h' => self.scroll(-self.size.x / 4, 0),
-            'j' => self.select(1),
-            'k' => self.select(-1),
-            'l' => self.scroll(self.size.x / 4, 0),
+            Action::ScrollLeft => self.scroll(-self.size.x / 4, 0),
+            Action::Down => self.select(repeat),
+            Action::Up => self.select(-repeat),
+            Action::ScrollRight => self.scroll(self.size.x / 4, 0),
 
-            '0' => self.scroll_beg(),
-            '$' => self.scroll_end(),
-            'g' => self.select_first(),
-            'G' => self.select_last(),
+            Action::ScrollBegin => self.scroll_beg(),
+            Action::ScrollEnd => self.scroll_end(),
+            Action::First => self.select_first(),
+            Action::Last => self.select_last(),
 
-            'd' => self.select(self.size.y / 4),
-            'u' => self.select(-self.size.y / 4),
+            Action::QuarterPageDown => self.select(self.size.y / 4),
+            Action::QuarterPageUp => self.select(-self.size.y / 4),
+            Action::PageDown => self.select(self.size.y),
+            Action::PageUp => self.select(-self.size.y),
 
-            _ => (),
+            Action::ClearHighlight => {
+                if self.highlight.take().is_some() {
+                    self.dirty = true;
+                }
+            }
+            Action::FoldPrefix => {
+                self.count = count;
+                self.fold_prefix = true;
+                self.dirty = true;
+            }
+        }
+
+        tui::Response::None
+    }
+
+    fn handle_mouse(&mut self, ev: tui::MouseEvent) -> tui::Response {
+        use crate::tui::MouseEvent::*;
+
+        let rect = match self.tree_rect {
+            Some(rect) if rect.contains(ev.pos()) => rect,
+            _ => return tui::Response::None,
+        };
+        match ev {
+            Click(p) => return self.click(p - rect.min),
+            WheelUp(_) => self.scroll(0, -tui::WHEEL_LINES),
+            WheelDown(_) => self.scroll(0, tui::WHEEL_LINES),
+        }
+        tui::Response::None
+    }
+
+    fn help(&self) -> Vec<HelpSection> {
+        vec![
+            ("Tree", binding::help(BINDINGS)),
+            (
+                "Folds, after z and an optional count",
+                binding::help(FOLD_BINDINGS),
+            ),
+            ("Details pane, when focused", binding::help(SCROLL_BINDINGS)),
+        ]
+    }
+
+    fn panes(&mut self) -> Vec<&mut dyn tui::Pane> {
+        self.detail
+            .iter_mut()
+            .map(|detail| detail as &mut dyn tui::Pane)
+            .collect()
+    }
+
+    fn handle_prompt(&mut self, input: &str, ev: tui::PromptEvent) -> tui::Response {
+        let prompt = if ev == tui::PromptEvent::Changed {
+            self.prompt.clone()
+        } else {
+            self.prompt.take()
+        };
+        match prompt {
+            Some(PromptKind::Search {
+                backward,
+                origin,
+                expanded,
+            }) => self.handle_search_prompt(input, ev, backward, origin, &expanded),
+            Some(PromptKind::Filter { previous }) => self.handle_filter_prompt(input, ev, previous),
+            None => tui::Response::None,
         }
     }
 }
 
-fn gen_path_prefix<T: Tree>(tree: &T, path: &[usize]) -> String {
+fn gen_path_prefix<T: Tree>(tv: &TreeView<T>, path: &[usize]) -> String {
     match path.len() {
         0 => panic!("Empty node path"),
-        1...2 => "".to_string(),
+        1..=2 => "".to_string(),
         _ => path[1..path.len() - 1]
             .iter()
             .map(|&node| {
-                if tree.next_sibling(node).is_some() {
-                    "    │   "
+                if tv.has_visible_sibling(node) {
+                    tv.style.vertical
                 } else {
-                    "        "
+                    tv.style.blank
                 }
             })
             .collect::<String>(),
@@ -251,18 +1390,14 @@ fn gen_path_prefix<T: Tree>(tree: &T, path: &[usize]) -> String {
 
 fn gen_line_prefix<T: Tree>(tv: &TreeView<T>, path: &[usize], is_first_line: bool) -> String {
     let last_id = *path.last().unwrap();
-    let expand_marker = if tv.expanded[last_id] { "[+] " } else { "[-] " };
-    match (
-        path.len(),
-        is_first_line,
-        tv.tree.next_sibling(last_id).is_some(),
-    ) {
-        (0...1, true, _) => expand_marker.to_string(),
-        (0...1, false, _) => "    ".to_string(),
-        (_, true, true) => format!("    ├── {}", expand_marker),
-        (_, true, false) => format!("    └── {}", expand_marker),
-        (_, false, true) => "    │       ".to_string(),
-        (_, false, false) => "            ".to_string(),
+    let style = &tv.style;
+    let expand_marker = style.marker(tv.expanded[last_id]);
+    match (path.len(), is_first_line, tv.has_visible_sibling(last_id)) {
+        (0..=1, true, _) => expand_marker.to_string(),
+        (0..=1, false, _) => " ".repeat(style.marker(true).len()),
+        (_, true, true) => format!("{}{}", style.tee, expand_marker),
+        (_, true, false) => format!("{}{}", style.elbow, expand_marker),
+        (_, false, has_sibling) => style.continuation(has_sibling),
     }
 }
 
@@ -289,6 +1424,9 @@ struct TVLineIter<'a, T: Tree> {
 impl<'a, T: Tree> TVLineIter<'a, T> {
     fn new(tv: &'a mut TreeView<T>) -> Self {
         tv.lookup.clear();
+        tv.text_start.clear();
+        tv.kinds.clear();
+        tv.annotations.clear();
         let node_iter = tv.tree.node_iter();
         TVLineIter {
             state: TVLineIterState::Node,
@@ -319,28 +1457,46 @@ impl<'a, T: Tree> Iterator for TVLineIter<'a, T> {
                         .rev()
                         .skip(1)
                         .all(|id| self.tv.expanded[*id])
+                        && self.tv.is_visible(*self.path.last().unwrap())
                     {
                         break;
                     }
                 }
                 self.state = TVLineIterState::Line;
-                self.node_prefix = gen_path_prefix(&self.tv.tree, &self.path);
+                self.node_prefix = gen_path_prefix(self.tv, &self.path);
                 self.line_iter = Some(self.tv.tree.line_iter(*self.path.last().unwrap()));
                 self.is_first_line = true;
                 self.next()
             }
             TVLineIterState::Line => {
                 if self.is_first_line {
-                    self.line_prefix = gen_line_prefix(&self.tv, &self.path, true).to_string();
+                    self.line_prefix = gen_line_prefix(self.tv, &self.path, true).to_string();
                 }
 
                 let res = {
-                    if let Some(string) = self.line_iter.as_mut().unwrap().next() {
-                        self.tv.lookup.push(*self.path.last().unwrap());
-                        Some(format!(
-                            "{}{}{}",
-                            self.node_prefix, self.line_prefix, string
-                        ))
+                    if let Some(spans) = self.line_iter.as_mut().unwrap().next() {
+                        let id = *self.path.last().unwrap();
+                        self.tv.lookup.push(id);
+                        self.tv
+                            .text_start
+                            .push(self.node_prefix.len() + self.line_prefix.len());
+                        let mut end = 0;
+                        self.tv.kinds.push(
+                            spans
+                                .iter()
+                                .map(|s| {
+                                    end += text::width(&s.text);
+                                    (end, s.kind)
+                                })
+                                .collect(),
+                        );
+                        self.tv.annotations.push(if self.is_first_line {
+                            self.tv.tree.annotation(id)
+                        } else {
+                            None
+                        });
+                        let string = line_text(&spans);
+                        Some(format!("{}{}{}", self.node_prefix, self.line_prefix, string))
                     } else {
                         self.state = TVLineIterState::Node;
                         return self.next();
@@ -349,7 +1505,7 @@ impl<'a, T: Tree> Iterator for TVLineIter<'a, T> {
 
                 if self.is_first_line {
                     self.is_first_line = false;
-                    self.line_prefix = gen_line_prefix(&self.tv, &self.path, false).to_string();
+                    self.line_prefix = gen_line_prefix(self.tv, &self.path, false).to_string();
                 }
 
                 res
@@ -363,6 +1519,8 @@ mod tests {
     use super::*;
 
     use crate::process::tree::{NodeId, Tree, TreeIter};
+    use crate::tui::headless::Headless;
+    use crate::tui::{theme, Tui};
 
     use std::collections::HashMap;
 
@@ -380,8 +1538,8 @@ mod tests {
         }
     }
 
-    impl<'a> IntoIterator for &'a ProcessMock {
-        type Item = String;
+    impl IntoIterator for &ProcessMock {
+        type Item = Vec<Span>;
         type IntoIter = NodeIterMock;
 
         fn into_iter(self) -> Self::IntoIter {
@@ -398,11 +1556,14 @@ mod tests {
     }
 
     impl Iterator for NodeIterMock {
-        type Item = String;
-        fn next(&mut self) -> Option<String> {
+        type Item = Vec<Span>;
+        fn next(&mut self) -> Option<Vec<Span>> {
             if self.num_lines > 0 {
                 self.num_lines -= 1;
-                Some(format!("{}_line_{}", self.name, self.num_lines))
+                Some(vec![
+                    Span::new(self.name.as_str(), SpanKind::Program),
+                    Span::new(format!("_line_{}", self.num_lines), SpanKind::Text),
+                ])
             } else {
                 None
             }
@@ -417,7 +1578,9 @@ mod tests {
             self.num_nodes()
         }
         fn next_sibling(&self, node: usize) -> Option<usize> {
-            Tree::next_sibling(self, node)
+            let siblings = self.get(self.get(node).parent()?).children();
+            let pos = siblings.iter().position(|&id| id == node)?;
+            siblings.get(pos + 1).cloned()
         }
 
         fn node_iter(&self) -> Self::NodeIter {
@@ -441,7 +1604,7 @@ mod tests {
         let mut ids = HashMap::new();
         ids.insert("root".to_string(), 0);
 
-        for (name, parent) in &vec![
+        for (name, parent) in &[
             ("n1", "root"),
             ("n2", "root"),
             ("n3", "root"),
@@ -463,6 +1626,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn iter() {
         let (t, ids) = make_tree(1);
         let mut tv = TreeView::new(&t);
@@ -508,6 +1672,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn iter_2lines() {
         let (t, ids) = make_tree(2);
         let mut tv = TreeView::new(&t);
@@ -575,4 +1740,634 @@ mod tests {
             assert_eq!(tv.lookup[idx], expected_ids[idx]);
         }
     }
+
+    #[test]
+    fn iter_styles() {
+        use crate::tui::tree_style;
+
+        let (t, _) = make_tree(2);
+
+        let mut tv = TreeView::new(&t);
+        let mut style = tree_style::ASCII;
+        style.markers = false;
+        tv.set_style(style);
+        let lines: Vec<_> = TVLineIter::new(&mut tv).take(12).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "root_line_1",
+                "root_line_0",
+                "\\_ n1_line_1",
+                "|  n1_line_0",
+                "| \\_ n11_line_1",
+                "| |  n11_line_0",
+                "| | \\_ n111_line_1",
+                "| |    n111_line_0",
+                "| |   \\_ n1111_line_1",
+                "| |      n1111_line_0",
+                "| \\_ n12_line_1",
+                "|    n12_line_0",
+            ]
+        );
+
+        let mut tv = TreeView::new(&t);
+        tv.set_style(tree_style::FOREST);
+        let lines: Vec<_> = TVLineIter::new(&mut tv).take(6).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "[+] root_line_1",
+                "    root_line_0",
+                " \\_ [+] n1_line_1",
+                "        n1_line_0",
+                "     \\_ [+] n11_line_1",
+                "            n11_line_0",
+            ]
+        );
+
+        let mut tv = TreeView::new(&t);
+        tv.set_style(tree_style::INDENT);
+        let lines: Vec<_> = TVLineIter::new(&mut tv).take(6).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "[+] root_line_1",
+                "    root_line_0",
+                "  [+] n1_line_1",
+                "      n1_line_0",
+                "    [+] n11_line_1",
+                "        n11_line_0",
+            ]
+        );
+    }
+
+    #[test]
+    fn search() {
+        use crate::tui::{Client, PromptEvent, Response};
+
+        let (t, ids) = make_tree(1);
+        let mut tv = TreeView::new(&t);
+        tv.expanded[ids["n1"]] = false;
+        tv.fetch_lines();
+
+        assert_eq!(
+            tv.handle_key(Key::Char('/')),
+            Response::Prompt("/".to_string())
+        );
+        assert_eq!(tv.handle_prompt("n3", PromptEvent::Changed), Response::None);
+        assert_eq!(tv.selected_node(), ids["n3"]);
+        assert_eq!(
+            tv.handle_prompt("n31", PromptEvent::Changed),
+            Response::None
+        );
+        assert_eq!(tv.selected_node(), ids["n31"]);
+        assert_eq!(
+            tv.handle_prompt("n31", PromptEvent::Cancelled),
+            Response::None
+        );
+        assert_eq!(tv.selected_node(), ids["root"]);
+        assert_eq!(tv.highlight, None);
+
+        // Ancestors expanded while typing are collapsed again on cancel
+        tv.handle_key(Key::Char('/'));
+        tv.handle_prompt("n11", PromptEvent::Changed);
+        assert_eq!(tv.selected_node(), ids["n11"]);
+        tv.handle_prompt("n111", PromptEvent::Changed);
+        assert!(tv.expanded[ids["n1"]]);
+        tv.handle_prompt("n111", PromptEvent::Cancelled);
+        assert!(!tv.expanded[ids["n1"]]);
+        assert_eq!(tv.selected_node(), ids["root"]);
+
+        // Matches in collapsed subtrees are revealed
+        tv.handle_key(Key::Char('/'));
+        assert_eq!(
+            tv.handle_prompt("N11", PromptEvent::Changed),
+            Response::None
+        );
+        assert_eq!(tv.selected_node(), ids["root"]);
+        assert_eq!(
+            tv.handle_prompt("n11", PromptEvent::Submitted),
+            Response::None
+        );
+        assert_eq!(tv.selected_node(), ids["n11"]);
+        assert!(tv.expanded[ids["n1"]]);
+
+        assert_eq!(tv.handle_key(Key::Char('n')), Response::None);
+        assert_eq!(tv.selected_node(), ids["n111"]);
+        assert_eq!(tv.handle_key(Key::Char('n')), Response::None);
+        assert_eq!(tv.selected_node(), ids["n1111"]);
+        assert_eq!(tv.handle_key(Key::Char('n')), Response::None);
+        assert_eq!(tv.selected_node(), ids["n11"]);
+        assert_eq!(tv.handle_key(Key::Char('N')), Response::None);
+        assert_eq!(tv.selected_node(), ids["n1111"]);
+
+        // Backward search, empty input repeats the last pattern
+        tv.handle_key(Key::Char('#'));
+        assert_eq!(tv.handle_prompt("", PromptEvent::Submitted), Response::None);
+        assert_eq!(tv.selected_node(), ids["n111"]);
+        assert_eq!(tv.handle_key(Key::Char('N')), Response::None);
+        assert_eq!(tv.selected_node(), ids["n1111"]);
+
+        tv.handle_key(Key::Char('/'));
+        assert_eq!(
+            tv.handle_prompt("xyz", PromptEvent::Submitted),
+            Response::Message("Pattern not found: xyz".to_string())
+        );
+        assert_eq!(tv.selected_node(), ids["n1111"]);
+    }
+
+    #[test]
+    fn filter() {
+        use crate::tui::{Client, PromptEvent, Response};
+
+        let (t, ids) = make_tree(1);
+        let mut tv = TreeView::new(&t);
+        tv.reveal_node(ids["n2"]);
+        assert_eq!(tv.selected_node(), ids["n2"]);
+
+        assert_eq!(
+            tv.handle_key(Key::Char('f')),
+            Response::Prompt("filter: ".to_string())
+        );
+        assert_eq!(
+            tv.handle_prompt("n31", PromptEvent::Changed),
+            Response::None
+        );
+        let expected: Vec<_> = ["root", "n3", "n31", "n311", "n3111"]
+            .iter()
+            .map(|n| ids[*n])
+            .collect();
+        assert_eq!(tv.lookup, expected);
+        assert_eq!(tv.selected_node(), ids["root"]);
+        assert_eq!(tv.filter_matches, 3);
+
+        // Guides only connect visible siblings
+        assert_eq!(
+            gen_line_prefix(&tv, &[ids["root"], ids["n3"]], true),
+            "    └── [+] "
+        );
+
+        // Cancelling restores the previous filter
+        tv.handle_prompt("n311", PromptEvent::Changed);
+        tv.handle_prompt("n311", PromptEvent::Cancelled);
+        assert_eq!(tv.filter, None);
+        assert_eq!(tv.lookup.len(), ids.len());
+
+        tv.handle_key(Key::Char('f'));
+        tv.handle_prompt("n1", PromptEvent::Submitted);
+        assert_eq!(tv.filter, Some("n1".to_string()));
+        tv.select(1);
+        assert_eq!(tv.selected_node(), ids["n1"]);
+
+        // Search skips hidden nodes
+        tv.handle_key(Key::Char('/'));
+        tv.handle_prompt("n3", PromptEvent::Submitted);
+        assert_eq!(tv.selected_node(), ids["n1"]);
+
+        // The selection is kept when it stays visible
+        tv.handle_key(Key::Char('f'));
+        tv.handle_prompt("", PromptEvent::Submitted);
+        assert_eq!(tv.filter, None);
+        assert_eq!(tv.selected_node(), ids["n1"]);
+    }
+
+    #[test]
+    fn detail_focus() {
+        use termion::event::{Event, MouseButton, MouseEvent};
+
+        let (t, ids) = make_tree(1);
+        let mut tui = headless_tui(&t);
+        let focused = |tui: &Tui<_, _>| tui.focus == Some(0);
+        let click = |tui: &mut Tui<_, _>, x: u16, y: u16| {
+            let ev = MouseEvent::Press(MouseButton::Left, x + 1, y + 1);
+            tui.backend().send(tui::Event::Input(Event::Mouse(ev)));
+            tui.handle_pending();
+        };
+
+        // Without detail pane, tab keeps the focus on the tree
+        tui.press("\t");
+        assert_eq!(tui.focus, None);
+
+        tui.press("ij");
+        assert_eq!(tui.client.selected_node(), ids["n1"]);
+        tui.press("\t");
+        assert!(focused(&tui));
+
+        // Scrolling keys go to the detail pane, the rest to the tree
+        tui.press("j");
+        assert_eq!(tui.client.selected_node(), ids["n1"]);
+        tui.press(" ");
+        assert!(!tui.client.expanded[ids["n1"]]);
+
+        tui.press("\t");
+        assert!(!focused(&tui));
+        tui.press("j");
+        assert_eq!(tui.client.selected_node(), ids["n2"]);
+
+        // Clicks focus what was clicked, the pane takes the right 40% of the screen
+        click(&mut tui, 30, 2);
+        assert!(focused(&tui));
+        click(&mut tui, 2, 0);
+        assert!(!focused(&tui));
+        assert_eq!(tui.client.selected_node(), ids["root"]);
+
+        // Closing the focused pane gives the focus back to the tree
+        tui.press("\ti");
+        assert!(tui.client.detail.is_none());
+        assert_eq!(tui.focus, None);
+
+        tui.press("\n");
+        assert!(tui.popup.is_some());
+    }
+
+    #[test]
+    fn status() {
+        use crate::tui::{Client, PromptEvent, Response};
+
+        let (t, ids) = make_tree(1);
+        let mut tv = TreeView::new(&t);
+        assert_eq!(tv.status().0, "");
+        assert!(tv.status().1.starts_with("1/12  12 nodes  "));
+
+        tv.reveal_node(ids["n1111"]);
+        assert_eq!(
+            tv.handle_key(Key::Char(' ')),
+            Response::Message("Nothing to expand or collapse".to_string())
+        );
+        assert!(tv.expanded[ids["n1111"]]);
+        assert!(tv.status().1.starts_with("5/12  "));
+
+        tv.handle_key(Key::Char('/'));
+        tv.handle_prompt("n2", PromptEvent::Submitted);
+        tv.handle_key(Key::Char('f'));
+        tv.handle_prompt("n", PromptEvent::Submitted);
+        assert_eq!(tv.status().0, "/n2  filter: n");
+    }
+
+    #[test]
+    fn styles() {
+        use crate::tui::theme::{self, Attrs, Color};
+        use crate::tui::{Draw, Frame, Rect};
+
+        let (t, _) = make_tree(2);
+        let mut tv = TreeView::new(&t);
+        assert_eq!(
+            tv.line_spans(2, &theme::DARK),
+            vec![
+                (12, theme::DARK.guides),
+                (14, theme::DARK.program),
+                (21, Style::new()),
+                (21, theme::DARK.annotation),
+            ]
+        );
+
+        let mut frame = Frame::new(Point::new(40, 6));
+        let rect = Rect::new(Point::new(0, 0), Point::new(39, 5));
+        tv.draw(&rect, &mut frame);
+        let cell = |frame: &Frame, x: usize, y: usize| frame.line(y as i32)[x].clone();
+        assert_eq!(cell(&frame, 0, 0).bg, Color::Indexed(236));
+        assert_eq!(cell(&frame, 4, 2).fg, Color::Indexed(244));
+        assert_eq!(cell(&frame, 4, 2).bg, Color::Indexed(234));
+        assert_eq!(cell(&frame, 12, 2).fg, Color::Indexed(255));
+        assert_eq!(cell(&frame, 12, 2).attrs, Attrs::BOLD);
+        assert_eq!(cell(&frame, 14, 2).attrs, Attrs::NONE);
+
+        frame.set_theme(theme::NONE);
+        tv.draw(&rect, &mut frame);
+        assert_eq!(cell(&frame, 4, 2).fg, Color::Default);
+        assert_eq!(cell(&frame, 4, 2).bg, Color::Default);
+        assert_eq!(cell(&frame, 4, 2).attrs, Attrs::DIM);
+        assert_eq!(cell(&frame, 4, 0).attrs, Attrs::REVERSE.union(Attrs::BOLD));
+    }
+
+    #[test]
+    fn wide_chars() {
+        use crate::tui::theme::{self, Color};
+        use crate::tui::{Draw, Frame, Rect};
+
+        let mut t = Tree::new(ProcessMock::new(1, "根"));
+        t.insert(ProcessMock::new(1, "e\u{301}x"), Some(0));
+        let mut tv = TreeView::new(&t);
+        assert_eq!(tv.data_size.x, 21);
+        assert_eq!(tv.line_spans(0, &theme::DARK)[1], (6, theme::DARK.program));
+
+        let row = |frame: &Frame, y: i32| -> String {
+            frame
+                .line(y)
+                .iter()
+                .map(|c| c.symbol.as_str())
+                .collect()
+        };
+        let mut frame = Frame::new(Point::new(20, 3));
+        tv.highlight = Some("line".to_string());
+        tv.draw(&Rect::new(Point::new(0, 0), Point::new(19, 2)), &mut frame);
+        assert_eq!(row(&frame, 0), "[+] 根_line_0       ");
+        assert_eq!(row(&frame, 1), "    └── [+] e\u{301}x_line_");
+        // The match starts after the wide glyph
+        let bg = |x: usize| frame.line(0)[x].bg;
+        assert_eq!((bg(6), bg(7)), (Color::Indexed(236), Color::Indexed(178)));
+
+        // Half of a glyph cut off by scrolling is left blank
+        let mut frame = Frame::new(Point::new(10, 3));
+        tv.draw(&Rect::new(Point::new(0, 0), Point::new(9, 2)), &mut frame);
+        tv.scroll(5, 0);
+        tv.draw(&Rect::new(Point::new(0, 0), Point::new(9, 2)), &mut frame);
+        assert_eq!(row(&frame, 0), " _line_0  ");
+
+        // A glyph in the last column of the tree does not cover the detail pane's border
+        let t = Tree::new(ProcessMock::new(1, "x根根根根"));
+        let mut tv = TreeView::new(&t);
+        tv.toggle_detail();
+        let mut frame = Frame::new(Point::new(20, 4));
+        tv.draw(&Rect::new(Point::new(0, 0), Point::new(19, 3)), &mut frame);
+        assert_eq!(row(&frame, 0), "[+] x根根根 ┌ Detai┐");
+    }
+
+    /// TUI drawing the tree on a 40x8 screen, whose last two lines are the status and prompt
+    fn headless_tui(t: &Tree<ProcessMock>) -> Tui<TreeView<&Tree<ProcessMock>>, Headless> {
+        Tui::new(TreeView::new(t), theme::NONE).unwrap()
+    }
+
+    /// Snapshots start on the line after the opening quote, to keep their indentation
+    fn screen(text: &str) -> &str {
+        &text[1..]
+    }
+
+    #[test]
+    fn draw_scrolloff() {
+        let (t, _) = make_tree(1);
+        let mut tui = headless_tui(&t);
+        let top = screen("
+[+] root_line_0
+    ├── [+] n1_line_0
+    │       ├── [+] n11_line_0
+    │       │       └── [+] n111_line_0
+    │       │               └── [+] n111
+    │       └── [+] n12_line_0
+           1/12  12 nodes  ?:help q:quit
+
+");
+        assert_eq!(tui.screen(), top);
+
+        // A quarter of the lines are kept visible below the selection
+        assert_eq!(tui.press("jjjj"), top.replace("1/12", "5/12"));
+        assert_eq!(
+            tui.press("j"),
+            screen("
+    ├── [+] n1_line_0
+    │       ├── [+] n11_line_0
+    │       │       └── [+] n111_line_0
+    │       │               └── [+] n111
+    │       └── [+] n12_line_0
+    ├── [+] n2_line_0
+           6/12  12 nodes  ?:help q:quit
+
+")
+        );
+        let bottom = screen("
+    ├── [+] n2_line_0
+    └── [+] n3_line_0
+            ├── [+] n31_line_0
+            │       └── [+] n311_line_0
+            │               └── [+] n311
+            └── [+] n32_line_0
+          12/12  12 nodes  ?:help q:quit
+
+");
+        assert_eq!(tui.press("G"), bottom);
+
+        // And above it
+        assert_eq!(tui.press("kkkk"), bottom.replace("12/12", " 8/12"));
+        assert_eq!(
+            tui.press("k"),
+            screen("
+    │       └── [+] n12_line_0
+    ├── [+] n2_line_0
+    └── [+] n3_line_0
+            ├── [+] n31_line_0
+            │       └── [+] n311_line_0
+            │               └── [+] n311
+           7/12  12 nodes  ?:help q:quit
+
+")
+        );
+        assert_eq!(tui.press("g"), top);
+    }
+
+    #[test]
+    fn draw_horizontal_scroll() {
+        let (t, _) = make_tree(1);
+        let mut tui = headless_tui(&t);
+        let top = tui.screen().to_string();
+        // Scrolling stops at the end of the longest line
+        let end = screen("
+_line_0
+[+] n1_line_0
+    ├── [+] n11_line_0
+    │       └── [+] n111_line_0
+    │               └── [+] n1111_line_0
+    └── [+] n12_line_0
+           1/12  12 nodes  ?:help q:quit
+
+");
+        assert_eq!(tui.press("l"), end);
+        assert_eq!(tui.press("l"), end);
+        assert_eq!(tui.press("0"), top);
+        assert_eq!(tui.press("$"), end);
+    }
+
+    #[test]
+    fn draw_resized() {
+        let (t, _) = make_tree(1);
+        let mut tui = headless_tui(&t);
+        tui.press("G");
+        tui.backend_mut().resize(Point::new(30, 5));
+        // The selection stays visible
+        assert_eq!(
+            tui.press(""),
+            screen("
+            │       └── [+] n3
+            │               └─
+            └── [+] n32_line_0
+12/12  12 nodes  ?:help q:quit
+
+")
+        );
+    }
+
+    #[test]
+    fn yank() {
+        use crate::tui::{Client, Response};
+
+        let (t, ids) = make_tree(1);
+        let mut tv = TreeView::new(&t);
+        assert_eq!(
+            tv.handle_key(Key::Char('y')),
+            Response::Message("No command recorded".to_string())
+        );
+
+        // Collapsed descendants are left out, like on screen
+        tv.reveal_node(ids["n111"]);
+        tv.toggle_expand();
+        tv.reveal_node(ids["n11"]);
+        let expected = "[+] n11_line_0\n    └── [-] n111_line_0\n";
+        assert_eq!(tv.handle_key(Key::Char('Y')), Response::Yank(expected.to_string()));
+    }
+
+    #[test]
+    fn sort_keys() {
+        use crate::tui::{Client, Response};
+
+        let (t, _) = make_tree(1);
+        let mut tv = TreeView::new(&t);
+        assert_eq!(
+            tv.handle_key(Key::Char('S')),
+            Response::Message("Not sorted, press s to sort".to_string())
+        );
+        assert_eq!(
+            tv.handle_key(Key::Char('s')),
+            Response::Message("sort: pid".to_string())
+        );
+        tv.handle_key(Key::Char('S'));
+        tv.handle_key(Key::Char('s'));
+        assert_eq!(tv.status().0, "sort: start desc");
+        for _ in 0..4 {
+            tv.handle_key(Key::Char('s'));
+        }
+        assert_eq!(
+            tv.handle_key(Key::Char('s')),
+            Response::Message("Recorded order".to_string())
+        );
+        assert_eq!(tv.sort, None);
+    }
+
+    #[test]
+    fn help() {
+        use crate::tui::Client;
+
+        let (t, _) = make_tree(1);
+        let tv = TreeView::new(&t);
+        let help = tv.help();
+        assert_eq!(help[0].0, "Tree");
+        // Every binding is listed, so the overlay cannot miss any key
+        assert_eq!(help[0].1.len(), BINDINGS.len());
+        assert!(help[0].1.contains(&(
+            Key::Char('#'),
+            "Search backward, not ? which shows this help"
+        )));
+    }
+
+    #[test]
+    fn keys() {
+        use crate::tui::{Client, Draw, Frame, Rect};
+
+        let (t, ids) = make_tree(1);
+        let mut tv = TreeView::new(&t);
+        let mut frame = Frame::new(Point::new(40, 6));
+        tv.draw(&Rect::new(Point::new(0, 0), Point::new(39, 5)), &mut frame);
+
+        tv.handle_key(Key::Down);
+        tv.handle_key(Key::Ctrl('n'));
+        assert_eq!(tv.selected_node(), ids["n11"]);
+        tv.handle_key(Key::Up);
+        assert_eq!(tv.selected_node(), ids["n1"]);
+        tv.handle_key(Key::End);
+        assert_eq!(tv.selected_node(), ids["n32"]);
+        tv.handle_key(Key::Home);
+        assert_eq!(tv.selected_node(), ids["root"]);
+
+        // The tree gets five lines, one is taken by the status line
+        tv.handle_key(Key::PageDown);
+        assert_eq!(tv.selected_line, 5);
+        tv.handle_key(Key::Ctrl('b'));
+        assert_eq!(tv.selected_line, 0);
+    }
+
+    #[test]
+    fn mouse() {
+        use crate::tui::{Client, Draw, Frame, MouseEvent, Rect};
+
+        let (t, ids) = make_tree(1);
+        let mut tv = TreeView::new(&t);
+        let mut frame = Frame::new(Point::new(40, 6));
+        let rect = Rect::new(Point::new(0, 1), Point::new(39, 5));
+        tv.draw(&rect, &mut frame);
+
+        // Rows are relative to the rect, text is not part of the marker
+        tv.handle_mouse(MouseEvent::Click(Point::new(14, 3)));
+        assert_eq!(tv.selected_node(), ids["n11"]);
+        assert!(tv.expanded[ids["n11"]]);
+
+        // `    │       ├── [+] n11`, the marker starts after the guides
+        tv.handle_mouse(MouseEvent::Click(Point::new(17, 3)));
+        assert!(!tv.expanded[ids["n11"]]);
+        assert_eq!(tv.lines.len(), 10);
+
+        tv.handle_mouse(MouseEvent::WheelDown(Point::new(0, 2)));
+        assert_eq!(tv.scroll.y, 3);
+        tv.handle_mouse(MouseEvent::Click(Point::new(0, 1)));
+        assert_eq!(tv.selected_line, 3);
+
+        // Below the last line and outside the rect nothing happens
+        tv.handle_mouse(MouseEvent::WheelDown(Point::new(0, 2)));
+        tv.handle_mouse(MouseEvent::Click(Point::new(0, 5)));
+        tv.handle_mouse(MouseEvent::Click(Point::new(0, 0)));
+        assert_eq!(tv.selected_line, 3);
+    }
+
+    #[test]
+    fn folds() {
+        use crate::tui::{Client, Response};
+
+        let (t, ids) = make_tree(1);
+        let mut tv = TreeView::new(&t);
+        let keys = |tv: &mut TreeView<_>, keys: &str| {
+            keys.chars()
+                .map(|c| tv.handle_key(Key::Char(c)))
+                .last()
+                .unwrap()
+        };
+
+        tv.reveal_node(ids["n111"]);
+        keys(&mut tv, "zM");
+        assert_eq!(tv.lookup, vec![ids["root"]]);
+        assert_eq!(tv.selected_node(), ids["root"]);
+
+        keys(&mut tv, "2zM");
+        assert_eq!(
+            tv.lookup,
+            vec![ids["root"], ids["n1"], ids["n2"], ids["n3"]]
+        );
+        assert_eq!(tv.count, None);
+
+        keys(&mut tv, "jzo");
+        assert_eq!(tv.lookup.len(), 8);
+        assert!(tv.expanded[ids["n111"]]);
+        assert!(!tv.expanded[ids["n3"]]);
+
+        // Count repeats movements, `0` only continues a count
+        keys(&mut tv, "12j");
+        assert_eq!(tv.selected_node(), ids["n3"]);
+        keys(&mut tv, "2k");
+        assert_eq!(tv.selected_node(), ids["n12"]);
+        assert_eq!(tv.status().0, "");
+        keys(&mut tv, "1z");
+        assert_eq!(tv.status().0, "1z");
+
+        keys(&mut tv, "R");
+        assert_eq!(tv.lookup.len(), 12);
+        keys(&mut tv, "3kzc");
+        assert_eq!(tv.selected_node(), ids["n11"]);
+        assert!(tv.expanded[ids["n11"]]);
+        assert!(!tv.expanded[ids["n12"]]);
+
+        tv.set_fold_depth(3);
+        assert_eq!(tv.selected_node(), ids["n11"]);
+        assert_eq!(tv.lookup.len(), 8);
+
+        assert_eq!(
+            keys(&mut tv, "gzc"),
+            Response::Message("No siblings to collapse".to_string())
+        );
+    }
 }
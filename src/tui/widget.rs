@@ -0,0 +1,439 @@
+use crate::tui::binding::{self, Binding};
+use crate::tui::theme::Style;
+use crate::tui::{self, text, Frame};
+use crate::util::{Point, Rect};
+
+use termion::event::Key;
+
+use std::cmp;
+
+/// Scrolling through lines of text, shared by the widgets showing them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scroll {
+    Down,
+    Up,
+    PageDown,
+    PageUp,
+    Top,
+    Bottom,
+}
+
+impl Scroll {
+    /// New offset into `len` lines of which `height` are visible
+    pub fn apply(self, offset: i32, len: i32, height: i32) -> i32 {
+        let offset = match self {
+            Scroll::Down => offset + 1,
+            Scroll::Up => offset - 1,
+            Scroll::PageDown => offset + height / 2,
+            Scroll::PageUp => offset - height / 2,
+            Scroll::Top => 0,
+            Scroll::Bottom => len,
+        };
+        cmp::max(0, cmp::min(offset, len - height))
+    }
+}
+
+pub const SCROLL_BINDINGS: &[Binding<Scroll>] = &[
+    Binding {
+        key: Key::Char('j'),
+        action: Scroll::Down,
+        help: "Scroll down",
+    },
+    Binding {
+        key: Key::Down,
+        action: Scroll::Down,
+        help: "Scroll down",
+    },
+    Binding {
+        key: Key::Char('k'),
+        action: Scroll::Up,
+        help: "Scroll up",
+    },
+    Binding {
+        key: Key::Up,
+        action: Scroll::Up,
+        help: "Scroll up",
+    },
+    Binding {
+        key: Key::Char('d'),
+        action: Scroll::PageDown,
+        help: "Scroll half a page down",
+    },
+    Binding {
+        key: Key::PageDown,
+        action: Scroll::PageDown,
+        help: "Scroll half a page down",
+    },
+    Binding {
+        key: Key::Char('u'),
+        action: Scroll::PageUp,
+        help: "Scroll half a page up",
+    },
+    Binding {
+        key: Key::PageUp,
+        action: Scroll::PageUp,
+        help: "Scroll half a page up",
+    },
+    Binding {
+        key: Key::Char('g'),
+        action: Scroll::Top,
+        help: "Scroll to top",
+    },
+    Binding {
+        key: Key::Home,
+        action: Scroll::Top,
+        help: "Scroll to top",
+    },
+    Binding {
+        key: Key::Char('G'),
+        action: Scroll::Bottom,
+        help: "Scroll to bottom",
+    },
+    Binding {
+        key: Key::End,
+        action: Scroll::Bottom,
+        help: "Scroll to bottom",
+    },
+];
+
+pub const POPUP_CLOSE_BINDINGS: &[Binding<()>] = &[
+    Binding {
+        key: Key::Esc,
+        action: (),
+        help: "Close",
+    },
+    Binding {
+        key: Key::Char('q'),
+        action: (),
+        help: "Close",
+    },
+    Binding {
+        key: Key::Char('\n'),
+        action: (),
+        help: "Close",
+    },
+];
+
+/// Lines of text scrolled vertically, the content of the detail pane and of popups
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextView {
+    lines: Vec<String>,
+    scroll: i32,
+    /// Number of lines shown when last drawn
+    height: i32,
+}
+
+impl TextView {
+    pub fn new(lines: Vec<String>) -> Self {
+        TextView {
+            lines,
+            scroll: 0,
+            height: 0,
+        }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Replaces the text and scrolls back to its top
+    pub fn set_lines(&mut self, lines: Vec<String>) {
+        self.lines = lines;
+        self.scroll = 0;
+    }
+
+    /// Returns whether the view moved
+    pub fn scroll(&mut self, scroll: Scroll) -> bool {
+        let offset = scroll.apply(self.scroll, self.lines.len() as i32, self.height);
+        let moved = offset != self.scroll;
+        self.scroll = offset;
+        moved
+    }
+
+    /// Scrolls on `SCROLL_BINDINGS`, returns whether `key` is one of them
+    pub fn handle_key(&mut self, key: Key) -> bool {
+        match binding::lookup(SCROLL_BINDINGS, key) {
+            Some(scroll) => {
+                self.scroll(scroll);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Scrolls on wheel events, clicks are ignored. Returns whether the view moved.
+    pub fn handle_mouse(&mut self, ev: tui::MouseEvent) -> bool {
+        let scroll = match ev {
+            tui::MouseEvent::WheelUp(_) => Scroll::Up,
+            tui::MouseEvent::WheelDown(_) => Scroll::Down,
+            tui::MouseEvent::Click(_) => return false,
+        };
+        let mut moved = false;
+        for _ in 0..tui::WHEEL_LINES {
+            moved |= self.scroll(scroll);
+        }
+        moved
+    }
+
+    /// Draws the lines fitting into `rect`, indented by one column
+    pub fn draw(&mut self, rect: &Rect, frame: &mut Frame) {
+        self.height = rect.height();
+        self.scroll = cmp::min(
+            self.scroll,
+            cmp::max(0, self.lines.len() as i32 - self.height),
+        );
+        frame.clipped(rect, |frame| {
+            for (y, l) in self.lines.iter().skip(self.scroll as usize).enumerate() {
+                if y as i32 >= rect.height() {
+                    break;
+                }
+                frame.print(rect.min + Point::new(1, y as i32), l, Style::new());
+            }
+        });
+    }
+}
+
+/// Line drawn around a widget, with an optional title in the top edge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Border {
+    title: Option<String>,
+    focused: bool,
+}
+
+impl Border {
+    pub fn new(title: Option<String>) -> Self {
+        Border {
+            title,
+            focused: false,
+        }
+    }
+
+    /// Highlights the border of popups and panes receiving input
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    /// Draws the border along the edges of `rect` and returns the rect inside of it
+    pub fn draw(&self, rect: &Rect, frame: &mut Frame) -> Rect {
+        let style = if self.focused {
+            frame.theme().border_focused
+        } else {
+            frame.theme().border
+        };
+        frame.clipped(rect, |frame| {
+            let mut put = |x, y, c| frame.put(Point::new(x, y), c, style);
+            for x in rect.min.x + 1..rect.max.x {
+                put(x, rect.min.y, '─');
+                put(x, rect.max.y, '─');
+            }
+            for y in rect.min.y + 1..rect.max.y {
+                put(rect.min.x, y, '│');
+                put(rect.max.x, y, '│');
+            }
+            put(rect.min.x, rect.min.y, '┌');
+            put(rect.max.x, rect.min.y, '┐');
+            put(rect.min.x, rect.max.y, '└');
+            put(rect.max.x, rect.max.y, '┘');
+
+            if let Some(title) = &self.title {
+                // Keep the corners visible
+                let top = Rect::new(
+                    Point::new(rect.min.x + 1, rect.min.y),
+                    Point::new(rect.max.x - 1, rect.min.y),
+                );
+                frame.clipped(&top, |frame| {
+                    frame.print(
+                        rect.min + Point::new(1, 0),
+                        &format!(" {} ", title),
+                        style,
+                    )
+                });
+            }
+        });
+
+        rect.inset(1)
+    }
+}
+
+/// Single line with text at its left and right end
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusBar {
+    left: String,
+    right: String,
+}
+
+impl StatusBar {
+    pub fn new(left: String, right: String) -> Self {
+        StatusBar { left, right }
+    }
+
+    /// Draws into the first line of `rect`, the left text wins if both do not fit
+    pub fn draw(&self, rect: &Rect, frame: &mut Frame) {
+        let line = Rect::new(rect.min, Point::new(rect.max.x, rect.min.y));
+        let style = frame.theme().status;
+        frame.clipped(&line, |frame| {
+            for x in line.min.x..=line.max.x {
+                frame.put(Point::new(x, line.min.y), ' ', style);
+            }
+            let right_width = text::width(&self.right) as i32;
+            frame.print(
+                Point::new(line.max.x + 1 - right_width, line.min.y),
+                &self.right,
+                style,
+            );
+            let left_width = text::width(&self.left) as i32;
+            frame.print(line.min, &self.left, style);
+            // Separate the texts if the left one overwrote part of the right one
+            if left_width + right_width > line.width() {
+                frame.print(
+                    Point::new(line.min.x + left_width, line.min.y),
+                    " ",
+                    style,
+                );
+            }
+        });
+    }
+}
+
+/// Modal window centered over the other widgets, which receives all input while open
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Popup {
+    title: String,
+    text: TextView,
+}
+
+impl Popup {
+    pub fn new<T: Into<String>>(title: T, lines: Vec<String>) -> Self {
+        Popup {
+            title: title.into(),
+            text: TextView::new(lines),
+        }
+    }
+
+    /// Scrolls on wheel events, clicks are ignored
+    pub fn handle_mouse(&mut self, ev: tui::MouseEvent) {
+        self.text.handle_mouse(ev);
+    }
+
+    /// Returns whether the popup stays open
+    pub fn handle_key(&mut self, key: Key) -> bool {
+        if binding::lookup(POPUP_CLOSE_BINDINGS, key).is_some() {
+            return false;
+        }
+        self.text.handle_key(key);
+        true
+    }
+}
+
+impl tui::Draw for Popup {
+    fn draw(&mut self, rect: &Rect, frame: &mut Frame) {
+        let lines = self.text.lines();
+        let width = lines
+            .iter()
+            .map(|l| text::width(l))
+            .chain(Some(text::width(&self.title) + 2))
+            .max()
+            .unwrap_or(0) as i32;
+        let size = Point::new(width + 4, lines.len() as i32 + 2);
+        let rect = rect.inset(1).centered(size);
+        if rect.is_empty() {
+            return;
+        }
+
+        frame.clear_rect(&rect);
+        let mut border = Border::new(Some(self.title.clone()));
+        border.set_focused(true);
+        let inner = border.draw(&rect, frame);
+        self.text.draw(&inner, frame);
+    }
+
+    fn dirty(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tui::Draw;
+
+    fn row(frame: &Frame, y: i32) -> String {
+        frame
+            .line(y)
+            .iter()
+            .map(|c| c.symbol.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn border() {
+        let mut frame = Frame::new(Point::new(12, 4));
+        let rect = Rect::new(Point::new(0, 0), Point::new(11, 3));
+        let inner = Border::new(Some("Details".to_string())).draw(&rect, &mut frame);
+        assert_eq!(inner, Rect::new(Point::new(1, 1), Point::new(10, 2)));
+        assert_eq!(row(&frame, 0), "┌ Details ─┐");
+        assert_eq!(row(&frame, 1), "│          │");
+        assert_eq!(row(&frame, 3), "└──────────┘");
+
+        // Titles are cut off before the corner
+        let inner = Border::new(Some("Long title".to_string())).draw(&rect, &mut frame);
+        assert_eq!(row(&frame, 0), "┌ Long titl┐");
+        assert_eq!(inner.width(), 10);
+    }
+
+    #[test]
+    fn status_bar() {
+        let mut frame = Frame::new(Point::new(12, 1));
+        let rect = Rect::new(Point::new(0, 0), Point::new(11, 0));
+        StatusBar::new("left".to_string(), "right".to_string()).draw(&rect, &mut frame);
+        assert_eq!(row(&frame, 0), "left   right");
+        StatusBar::new("left side".to_string(), "right".to_string()).draw(&rect, &mut frame);
+        assert_eq!(row(&frame, 0), "left side ht");
+    }
+
+    #[test]
+    fn popup() {
+        use termion::event::Key::*;
+
+        let mut frame = Frame::new(Point::new(20, 6));
+        let rect = Rect::new(Point::new(0, 0), Point::new(19, 5));
+        let lines = (1..=5).map(|n| format!("line {}", n)).collect();
+        let mut popup = Popup::new("Test", lines);
+        popup.draw(&rect, &mut frame);
+        assert_eq!(row(&frame, 0), " ".repeat(20));
+        assert_eq!(row(&frame, 1), "     ┌ Test ──┐     ");
+        assert_eq!(row(&frame, 2), "     │ line 1 │     ");
+        assert_eq!(row(&frame, 3), "     │ line 2 │     ");
+
+        assert!(popup.handle_key(Char('G')));
+        popup.draw(&rect, &mut frame);
+        assert_eq!(row(&frame, 3), "     │ line 5 │     ");
+        assert!(popup.handle_key(Up));
+        popup.draw(&rect, &mut frame);
+        assert_eq!(row(&frame, 3), "     │ line 4 │     ");
+        assert!(!popup.handle_key(Esc));
+    }
+
+    #[test]
+    fn text_view() {
+        let mut frame = Frame::new(Point::new(8, 2));
+        let rect = Rect::new(Point::new(0, 0), Point::new(7, 1));
+        let lines = (1..=4).map(|n| format!("line {}", n)).collect();
+        let mut text = TextView::new(lines);
+        text.draw(&rect, &mut frame);
+        assert_eq!(row(&frame, 0), " line 1 ");
+        assert_eq!(row(&frame, 1), " line 2 ");
+
+        // Scrolling stops at the last line, new text starts at the top again
+        assert!(text.handle_mouse(tui::MouseEvent::WheelDown(Point::new(0, 0))));
+        assert!(!text.scroll(Scroll::Down));
+        text.draw(&rect, &mut frame);
+        assert_eq!(row(&frame, 0), " line 3 ");
+        text.set_lines(vec!["new".to_string()]);
+        let mut frame = Frame::new(Point::new(8, 2));
+        text.draw(&rect, &mut frame);
+        assert_eq!(row(&frame, 0), " new    ");
+        assert_eq!(row(&frame, 1), " ".repeat(8));
+    }
+}
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+use std::cmp;
 use std::iter;
 use std::ops;
 
@@ -36,7 +38,8 @@ impl ops::Sub for Point {
     }
 }
 
-#[derive(Debug)]
+/// Rectangle including both corners, empty if `max` is left of or above `min`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Rect {
     pub min: Point,
     pub max: Point,
@@ -47,9 +50,174 @@ impl Rect {
         Rect { min, max }
     }
 
+    pub fn width(&self) -> i32 {
+        cmp::max(0, self.max.x - self.min.x + 1)
+    }
+
+    pub fn height(&self) -> i32 {
+        cmp::max(0, self.max.y - self.min.y + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width() == 0 || self.height() == 0
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        p.x >= self.min.x && p.x <= self.max.x && p.y >= self.min.y && p.y <= self.max.y
+    }
+
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        Rect::new(
+            Point::new(
+                cmp::max(self.min.x, other.min.x),
+                cmp::max(self.min.y, other.min.y),
+            ),
+            Point::new(
+                cmp::min(self.max.x, other.max.x),
+                cmp::min(self.max.y, other.max.y),
+            ),
+        )
+    }
+
+    /// Shrinks the rect by `n` cells on each side
+    pub fn inset(&self, n: i32) -> Rect {
+        Rect::new(self.min + Point::new(n, n), self.max - Point::new(n, n))
+    }
+
+    /// Rect of at most `size` centered in this one
+    pub fn centered(&self, size: Point) -> Rect {
+        let size = Point::new(
+            cmp::min(size.x, self.width()),
+            cmp::min(size.y, self.height()),
+        );
+        let min = self.min + Point::new((self.width() - size.x) / 2, (self.height() - size.y) / 2);
+        Rect::new(min, min + size - Point::new(1, 1))
+    }
+
     pub fn points(&self) -> impl Iterator<Item = Point> + '_ {
         (self.min.y..=self.max.y)
             .flat_map(move |y| iter::repeat(y).zip(self.min.x..=self.max.x))
             .map(|(y, x)| Point::new(x, y))
     }
 }
+
+/// Formats a duration given in microseconds for humans
+pub fn format_duration(micros: u64) -> String {
+    match micros {
+        0..=999 => format!("{}us", micros),
+        1_000..=999_999 => format!("{}ms", micros / 1_000),
+        1_000_000..=59_999_999 => format!("{:.2}s", micros as f64 / 1e6),
+        _ => format!("{}m{:02}s", micros / 60_000_000, micros / 1_000_000 % 60),
+    }
+}
+
+/// Encodes `data` as standard base64 with padding
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Meaning of a part of a node's line, which the theme assigns a style to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    Text,
+    /// Name of the executable
+    Program,
+    Flag,
+    Path,
+    /// Argument which had to be quoted, like one containing spaces
+    Quoted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub kind: SpanKind,
+}
+
+impl Span {
+    pub fn new<S: Into<String>>(text: S, kind: SpanKind) -> Self {
+        Span {
+            text: text.into(),
+            kind,
+        }
+    }
+}
+
+/// Text of a line without its styles
+pub fn line_text(spans: &[Span]) -> String {
+    spans.iter().map(|s| s.text.as_str()).collect()
+}
+
+/// Replaces control characters in `text` by escape sequences like `\t` or `\u{1b}`
+pub fn escape(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(char::is_control) {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(
+        text.chars()
+            .map(|c| {
+                if c.is_control() {
+                    c.escape_debug().to_string()
+                } else {
+                    c.to_string()
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Quotes `arg` for a POSIX shell, if necessary
+pub fn quote(arg: &str) -> Cow<'_, str> {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_=+/.,:@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', r"'\''")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"cc -c 'a b.c'"), "Y2MgLWMgJ2EgYi5jJw==");
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("plain 日本"), Cow::Borrowed("plain 日本"));
+        assert_eq!(escape("a\tb\r"), r"a\tb\r");
+        assert_eq!(escape("\u{1b}[31m"), r"\u{1b}[31m");
+        assert_eq!(escape("\u{9b}2J"), r"\u{9b}2J");
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("a.c"), "a.c");
+        assert_eq!(quote("-DFOO=1"), "-DFOO=1");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("$HOME"), "'$HOME'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+}